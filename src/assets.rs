@@ -0,0 +1,269 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::gitlab::client::FileVersion;
+use crate::gitlab::{self, types};
+use crate::metadata::write_json;
+
+const RELEASES_DIR: &str = "releases";
+const PACKAGES_DIR: &str = "packages";
+const RELEASE_FILE: &str = "release.json";
+/// Downloaded asset links of a release by their file names
+const ASSETS_FILE: &str = "assets.json";
+
+/// A downloaded asset link with the version reported by its server
+#[derive(Serialize, Deserialize)]
+struct Asset {
+    url: String,
+    sha256: String,
+    version: FileVersion,
+}
+
+/// A folder of the project releases in dst
+pub fn releases_path(dst: &str, project_dir: &str) -> PathBuf {
+    [dst, RELEASES_DIR, project_dir].iter().collect()
+}
+
+/// A folder of the project generic packages in dst
+pub fn packages_path(dst: &str, project_dir: &str) -> PathBuf {
+    [dst, PACKAGES_DIR, project_dir].iter().collect()
+}
+
+/// Names of releases and links may contain path separators
+fn file_name(name: &str) -> String {
+    match name.replace(['/', '\\'], "_") {
+        n if n == "." || n == ".." => "_".to_string(),
+        n => n,
+    }
+}
+
+pub fn sha256(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Check that the package file is already downloaded by its size and checksum
+fn is_downloaded(path: &Path, file: &types::PackageFile) -> bool {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return false;
+    };
+    if meta.len() != file.size {
+        return false;
+    }
+    match &file.file_sha256 {
+        Some(expected) => sha256(path).is_ok_and(|s| &s == expected),
+        None => true,
+    }
+}
+
+fn read_assets(release_dir: &Path) -> Result<BTreeMap<String, Asset>> {
+    let path = release_dir.join(ASSETS_FILE);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// Check that the asset is already downloaded: the local file is not changed since the download
+/// and the server reports the same version of the file by a HEAD request
+async fn is_asset_downloaded(
+    client: &gitlab::Client,
+    url: &str,
+    path: &Path,
+    asset: Option<&Asset>,
+) -> Result<bool> {
+    let Some(asset) = asset.filter(|a| a.url == url) else {
+        return Ok(false);
+    };
+    if !sha256(path).is_ok_and(|s| s == asset.sha256) {
+        return Ok(false);
+    }
+    Ok(client.file_version(url).await?.as_ref() == Some(&asset.version))
+}
+
+/// Save metadata of releases into `<tag>/release.json` and download their asset links next to it,
+/// links already downloaded with the same version are skipped. Links without `ETag`
+/// and `Last-Modified` headers are downloaded on every export
+pub async fn export_releases(
+    client: &gitlab::Client,
+    project: &types::Project,
+    dir: &Path,
+) -> Result<()> {
+    let releases = client.get_releases(project.id).await?;
+    let mut downloaded = 0;
+    for value in &releases {
+        let release: types::Release = serde_json::from_value(value.clone())?;
+        let release_dir = dir.join(file_name(&release.tag_name));
+        write_json(&release_dir.join(RELEASE_FILE), value)?;
+        let mut assets = read_assets(&release_dir)?;
+        for link in &release.assets.links {
+            let name = file_name(&link.name);
+            let path = release_dir.join(&name);
+            if is_asset_downloaded(client, &link.url, &path, assets.get(&name)).await? {
+                continue;
+            }
+            let file = client.download(&link.url, &path).await?;
+            downloaded += 1;
+            match file.version {
+                Some(version) => assets.insert(
+                    name,
+                    Asset {
+                        url: link.url.clone(),
+                        sha256: sha256(&path)?,
+                        version,
+                    },
+                ),
+                None => assets.remove(&name),
+            };
+            // saved after every asset, so an interrupted export keeps the downloaded ones
+            write_json(&release_dir.join(ASSETS_FILE), &assets)?;
+        }
+    }
+    info!(
+        "{} releases of {} exported, {downloaded} assets downloaded",
+        releases.len(),
+        project.path_with_namespace
+    );
+    Ok(())
+}
+
+/// Download files of generic packages into `<name>/<version>/`,
+/// files already downloaded with the same size and checksum are skipped
+pub async fn export_packages(
+    client: &gitlab::Client,
+    project: &types::Project,
+    dir: &Path,
+) -> Result<()> {
+    let mut downloaded = 0;
+    for package in client.get_generic_packages(project.id).await? {
+        let package_dir = dir
+            .join(file_name(&package.name))
+            .join(file_name(&package.version));
+        for file in client.get_package_files(project.id, package.id).await? {
+            let path = package_dir.join(file_name(&file.file_name));
+            if is_downloaded(&path, &file) {
+                continue;
+            }
+            let url = format!(
+                "projects/{}/packages/generic/{}/{}/{}",
+                project.id,
+                urlencoding::encode(&package.name),
+                urlencoding::encode(&package.version),
+                urlencoding::encode(&file.file_name)
+            );
+            client.download(&url, &path).await?;
+            downloaded += 1;
+        }
+    }
+    info!(
+        "generic packages of {} exported, {downloaded} files downloaded",
+        project.path_with_namespace
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::tests::TempDir;
+    use url::Url;
+
+    #[test]
+    fn file_names() {
+        assert_eq!(file_name("v1.0"), "v1.0");
+        assert_eq!(file_name("release/v1.0"), "release_v1.0");
+        assert_eq!(file_name("bin\\app.exe"), "bin_app.exe");
+        assert_eq!(file_name(".."), "_");
+        assert_eq!(file_name("."), "_");
+    }
+
+    #[test]
+    fn downloaded_package_files() {
+        let dir = TempDir::new("package-files");
+        let path = PathBuf::from(dir.path("app.bin"));
+        let mut file = types::PackageFile {
+            file_name: "app.bin".to_string(),
+            size: 3,
+            file_sha256: None,
+        };
+        assert!(!is_downloaded(&path, &file));
+
+        std::fs::write(&path, "app").unwrap();
+        assert!(is_downloaded(&path, &file));
+        file.file_sha256 = Some(sha256(&path).unwrap());
+        assert!(is_downloaded(&path, &file));
+
+        // a changed file of the same size
+        std::fs::write(&path, "bin").unwrap();
+        assert!(!is_downloaded(&path, &file));
+        file.size = 4;
+        assert!(!is_downloaded(&path, &file));
+    }
+
+    #[tokio::test]
+    async fn release_assets_skipped_by_version() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::parse(&format!("{}/", server.url())).unwrap();
+        let client = gitlab::Client::new("token", &url, None, true, None, None, None).unwrap();
+        let dir = TempDir::new("release-assets");
+        let project = types::Project::stub(1, "team/alpha");
+        let asset_url = format!("{}/files/app.bin", server.url());
+
+        server
+            .mock("GET", "/api/v4/projects/1/releases")
+            .match_query(mockito::Matcher::Any)
+            .with_body(
+                serde_json::json!([{
+                    "tag_name": "v1",
+                    "assets": {"links": [{"name": "app.bin", "url": asset_url}]}
+                }])
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let head = server
+            .mock("HEAD", "/files/app.bin")
+            .with_header("etag", "\"v1\"")
+            .expect(1)
+            .create_async()
+            .await;
+        let download = server
+            .mock("GET", "/files/app.bin")
+            .with_header("etag", "\"v1\"")
+            .with_body("app")
+            .expect(1)
+            .create_async()
+            .await;
+
+        // the second export finds the same version on the server
+        let releases = PathBuf::from(dir.path("releases"));
+        export_releases(&client, &project, &releases).await.unwrap();
+        export_releases(&client, &project, &releases).await.unwrap();
+        head.assert_async().await;
+        download.assert_async().await;
+
+        // a changed version is downloaded again even with the same size
+        server
+            .mock("HEAD", "/files/app.bin")
+            .with_header("etag", "\"v2\"")
+            .create_async()
+            .await;
+        let changed = server
+            .mock("GET", "/files/app.bin")
+            .with_header("etag", "\"v2\"")
+            .with_body("bin")
+            .expect(1)
+            .create_async()
+            .await;
+        export_releases(&client, &project, &releases).await.unwrap();
+        changed.assert_async().await;
+        let content = std::fs::read_to_string(releases.join("v1").join("app.bin")).unwrap();
+        assert_eq!(content, "bin");
+    }
+}
@@ -0,0 +1,282 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::assets::sha256;
+use crate::git;
+use crate::metadata::write_json;
+
+/// A manifest of bundles in dst, next to the bundles themselves
+const MANIFEST_FILE: &str = "bundles.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Bundle {
+    /// The bundle file relative to dst
+    file: String,
+    sha256: String,
+    size: u64,
+    created_at: DateTime<Utc>,
+    /// Whether the bundle needs the previous bundles of the repository to be unbundled
+    incremental: bool,
+    /// Object ids of the repository refs by their names at the time of bundling
+    refs: BTreeMap<String, String>,
+}
+
+/// Bundles written for repositories of dst with their checksums, saved between runs
+#[derive(Serialize, Deserialize, Default)]
+pub struct Manifest {
+    /// Bundles of repositories by the repository folder relative to dst, from the oldest one
+    repos: BTreeMap<String, Vec<Bundle>>,
+}
+
+impl Manifest {
+    fn path(dst: &str) -> PathBuf {
+        [dst, MANIFEST_FILE].iter().collect()
+    }
+
+    /// Load the manifest from dst, a missing manifest means no bundles were written before
+    pub fn load(dst: &str) -> Result<Self> {
+        let path = Self::path(dst);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, dst: &str) -> Result<()> {
+        write_json(&Self::path(dst), self)
+    }
+}
+
+/// Write a bundle of the repository `repo` into `<dst>/<repo_dir>.bundle`, verify it and save
+/// the manifest. An unchanged repository is not bundled again. Incremental bundles are written
+/// into `<dst>/<repo_dir>.<time>.bundle` with objects missing in the previous bundle only,
+/// a full bundle is written instead if the history of the previous bundle was rewritten
+pub async fn write(
+    repo: &str,
+    dst: &str,
+    repo_dir: &str,
+    incremental: bool,
+    manifest: &Mutex<Manifest>,
+) -> Result<()> {
+    let last = manifest
+        .lock()
+        .await
+        .repos
+        .get(repo_dir)
+        .and_then(|b| b.last().cloned());
+    let refs = git::refs(repo).await?;
+
+    if let Some(last) = &last {
+        // incremental bundles may be moved to a cold storage already, so only refs are compared
+        if last.refs == refs && (incremental || Path::new(dst).join(&last.file).exists()) {
+            info!("repository {repo_dir} is not changed since its last bundle");
+            return Ok(());
+        }
+    }
+
+    let base: Option<Vec<String>> = match &last {
+        Some(last) if incremental => {
+            let base: HashSet<String> = last.refs.values().cloned().collect();
+            let base: Vec<String> = base.into_iter().collect();
+            if git::has_objects(repo, &base).await? {
+                Some(base)
+            } else {
+                info!(
+                    "history of {repo_dir} is rewritten since its last bundle, bundling it fully"
+                );
+                None
+            }
+        }
+        _ => None,
+    };
+
+    let created_at = Utc::now();
+    let (file, exclude) = match base {
+        Some(base) => {
+            let time = created_at.format("%Y%m%dT%H%M%SZ");
+            (format!("{repo_dir}.{time}.bundle"), base)
+        }
+        None => (format!("{repo_dir}.bundle"), Vec::new()),
+    };
+    let path = std::path::absolute(Path::new(dst).join(&file))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    // the previous bundle is replaced only by a verified one
+    let tmp_path = path.with_extension("bundle.tmp");
+    if !git::bundle_create(repo, &tmp_path, &exclude).await? {
+        info!("repository {repo_dir} has no new objects since its last bundle");
+        return Ok(());
+    }
+    git::bundle_verify(repo, &tmp_path).await?;
+    std::fs::rename(&tmp_path, &path)?;
+
+    let bundle = Bundle {
+        sha256: sha256(&path)?,
+        size: std::fs::metadata(&path)?.len(),
+        file,
+        created_at,
+        incremental: !exclude.is_empty(),
+        refs,
+    };
+    info!("bundle {} written", bundle.file);
+    let mut manifest = manifest.lock().await;
+    let bundles = manifest.repos.entry(repo_dir.to_string()).or_default();
+    if !bundle.incremental {
+        bundles.clear();
+    }
+    bundles.push(bundle);
+    // bundles of an interrupted run are kept in the manifest
+    manifest.save(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::tests::{run_git, source_repo, TempDir};
+
+    /// Bundles of the repository in the manifest saved in dst
+    fn saved_bundles(dst: &str, repo_dir: &str) -> Vec<Bundle> {
+        Manifest::load(dst).unwrap().repos[repo_dir].clone()
+    }
+
+    fn commit(work: &str, message: &str) {
+        run_git(work, &["commit", "--quiet", "--allow-empty", "-m", message]);
+        run_git(work, &["push", "--quiet", "origin", "main"]);
+    }
+
+    #[tokio::test]
+    async fn full_bundle() {
+        let dir = TempDir::new("bundle-full");
+        let (bare, work) = source_repo(&dir);
+        let dst = dir.path("dst");
+        let manifest = Mutex::new(Manifest::default());
+
+        write(&bare, &dst, "team/alpha", false, &manifest)
+            .await
+            .unwrap();
+        let bundles = saved_bundles(&dst, "team/alpha");
+        assert_eq!(bundles.len(), 1);
+        assert_eq!(bundles[0].file, "team/alpha.bundle");
+        assert!(!bundles[0].incremental);
+        let path = Path::new(&dst).join(&bundles[0].file);
+        assert_eq!(bundles[0].sha256, sha256(&path).unwrap());
+
+        // an unchanged repository is not bundled again
+        write(&bare, &dst, "team/alpha", false, &manifest)
+            .await
+            .unwrap();
+        assert_eq!(
+            saved_bundles(&dst, "team/alpha")[0].created_at,
+            bundles[0].created_at
+        );
+
+        commit(&work, "second");
+        write(&bare, &dst, "team/alpha", false, &manifest)
+            .await
+            .unwrap();
+        let bundles = saved_bundles(&dst, "team/alpha");
+        assert_eq!(bundles.len(), 1);
+        assert_eq!(bundles[0].refs, git::refs(&bare).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn incremental_bundle() {
+        let dir = TempDir::new("bundle-incremental");
+        let (bare, work) = source_repo(&dir);
+        let dst = dir.path("dst");
+        let manifest = Mutex::new(Manifest::default());
+
+        write(&bare, &dst, "alpha", true, &manifest).await.unwrap();
+        commit(&work, "second");
+        write(&bare, &dst, "alpha", true, &manifest).await.unwrap();
+
+        let bundles = saved_bundles(&dst, "alpha");
+        assert_eq!(bundles.len(), 2);
+        assert!(!bundles[0].incremental);
+        assert!(bundles[1].incremental);
+        assert!(
+            bundles[1].file.starts_with("alpha.2"),
+            "{}",
+            bundles[1].file
+        );
+
+        // the incremental bundle is unbundled on top of the full one
+        let restored = dir.path("restored");
+        run_git(".", &["init", "--quiet", "--bare", &restored]);
+        for bundle in &bundles {
+            let file = Path::new(&dst).join(&bundle.file);
+            let file = file.to_string_lossy();
+            run_git(&restored, &["fetch", "--quiet", &file, "refs/*:refs/*"]);
+        }
+        assert_eq!(git::refs(&restored).await.unwrap(), bundles[1].refs);
+    }
+
+    #[tokio::test]
+    async fn full_bundle_after_rewritten_history() {
+        let dir = TempDir::new("bundle-rewritten");
+        let (bare, work) = source_repo(&dir);
+        let dst = dir.path("dst");
+        let manifest = Mutex::new(Manifest::default());
+        write(&bare, &dst, "alpha", true, &manifest).await.unwrap();
+
+        // the bundled history is replaced and collected
+        run_git(&work, &["checkout", "--quiet", "--orphan", "rewritten"]);
+        run_git(&work, &["commit", "--quiet", "--allow-empty", "-m", "new"]);
+        run_git(
+            &work,
+            &["push", "--quiet", "--force", "origin", "rewritten:main"],
+        );
+        run_git(
+            &work,
+            &["push", "--quiet", "origin", ":feature", ":refs/tags/v1"],
+        );
+        run_git(&bare, &["gc", "--quiet", "--prune=now"]);
+
+        write(&bare, &dst, "alpha", true, &manifest).await.unwrap();
+        let bundles = saved_bundles(&dst, "alpha");
+        assert_eq!(bundles.len(), 1);
+        assert_eq!(bundles[0].file, "alpha.bundle");
+        assert!(!bundles[0].incremental);
+        assert_eq!(bundles[0].refs, git::refs(&bare).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn remote_refs_of_working_copies_not_bundled() {
+        let dir = TempDir::new("bundle-working-copy");
+        let (bare, _) = source_repo(&dir);
+        let (local, backup) = (dir.path("local"), dir.path("backup.git"));
+        git::fetch(bare, local.clone(), false, false).await.unwrap();
+        git::init_bare(&backup, Some("main")).await.unwrap();
+        git::push_backup(local.clone(), backup, false, false)
+            .await
+            .unwrap();
+        let dst = dir.path("dst");
+        let manifest = Mutex::new(Manifest::default());
+
+        write(&local, &dst, "alpha", false, &manifest)
+            .await
+            .unwrap();
+        let bundles = saved_bundles(&dst, "alpha");
+        assert!(bundles[0]
+            .refs
+            .keys()
+            .all(|r| !r.starts_with("refs/remotes/")));
+
+        // remote-tracking refs moved by the next push do not make a new bundle
+        run_git(&local, &["update-ref", "refs/remotes/backup/moved", "HEAD"]);
+        write(&local, &dst, "alpha", false, &manifest)
+            .await
+            .unwrap();
+        assert_eq!(
+            saved_bundles(&dst, "alpha")[0].created_at,
+            bundles[0].created_at
+        );
+    }
+}
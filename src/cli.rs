@@ -1,15 +1,32 @@
 use clap::Parser;
 
 use crate::cloner::{
-    clone, BackupGitlabOptions, CloneParams, FetchGitlabOptions, FilterPatterns, ForceProtocol,
+    clone, BackupOptions, CloneParams, Failure, FetchOptions, FilterPatterns, ForceProtocol,
+    Provider, RepoNaming,
 };
-use anyhow::{bail, Result};
+use crate::config::{BackupSection, Config, FetchSection, RetrySection};
+use crate::gitlab::types::Visibility;
+use crate::retry;
+use anyhow::{anyhow, bail, Context, Result};
+use std::time::Duration;
+
+const DEFAULT_CONCURRENCY_LIMIT: usize = 21;
+/// One hour is enough for exports and imports of most projects
+const DEFAULT_EXPORT_TIMEOUT: u64 = 3600;
+/// Page sizes accepted by GitLab, GitHub and Gitea
+const OBJECTS_PER_PAGE: std::ops::RangeInclusive<i64> = 1..=100;
+/// The smallest rate, one request in about 17 minutes, so request intervals stay representable
+const MIN_API_RATE: f64 = 0.001;
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Parser)]
 #[command(author, version, about)]
 /// A tool for cloning all available repositories in a GitLab instance
 struct Cli {
+    /// A TOML or YAML config file with options, flags passed explicitly take precedence over it
+    #[arg(long, value_parser, env = "GTLBSTR_CONFIG", value_name = "FILE")]
+    config: Option<String>,
+
     /// The GitLab instance URL for fetch repositories (example: <https://gitlab.local>)
     #[arg(
         long,
@@ -17,7 +34,7 @@ struct Cli {
         env = "GTLBSTR_FETCH_URL",
         value_name = "FETCH URL"
     )]
-    fu: String,
+    fu: Option<String>,
 
     /// Your personal GitLab token for fetch repositories
     #[arg(
@@ -26,7 +43,17 @@ struct Cli {
         env = "GTLBSTR_FETCH_TOKEN",
         value_name = "FETCH TOKEN"
     )]
-    ft: String,
+    ft: Option<String>,
+
+    /// A service of fetch repositories, the --fu flag of GitHub is its REST API URL
+    /// (example: <https://api.github.com>), Forgejo is served by the gitea provider
+    #[arg(
+        long,
+        value_enum,
+        env = "GTLBSTR_FETCH_PROVIDER",
+        value_name = "PROVIDER"
+    )]
+    fetch_provider: Option<Provider>,
 
     /// The GitLab instance URL for backup repositories (example: <https://backup-gitlab.local>)
     #[arg(
@@ -46,7 +73,18 @@ struct Cli {
     )]
     bt: Option<String>,
 
-    /// A target created group on backup GitLab for push repositories
+    /// A service of backup repositories, the --bu flag of GitHub is its REST API URL
+    /// (example: <https://api.github.com>), Forgejo is served by the gitea provider, the --bu flag
+    /// of git is a URL template of repositories (example: <ssh://git@archive/{path_with_namespace}.git>)
+    #[arg(
+        long,
+        value_enum,
+        env = "GTLBSTR_BACKUP_PROVIDER",
+        value_name = "PROVIDER"
+    )]
+    backup_provider: Option<Provider>,
+
+    /// A target created group on backup GitLab (or an organization on GitHub and Gitea) for push repositories
     #[arg(
         long,
         value_parser,
@@ -55,6 +93,31 @@ struct Cli {
     )]
     bg: Option<String>,
 
+    /// Visibility of backup projects instead of the visibility of source projects,
+    /// projects are never more visible than their groups
+    #[arg(
+        long,
+        value_enum,
+        env = "GTLBSTR_BACKUP_VISIBILITY",
+        value_name = "VISIBILITY"
+    )]
+    backup_visibility: Option<Visibility>,
+
+    /// How GitHub and Gitea repositories are named: `flatten` turns group/sub/project into
+    /// group-sub-project, `project` uses only the project path, `organization` makes the group
+    /// a Gitea organization with the sub-project repository [default: flatten]
+    #[arg(
+        long,
+        value_enum,
+        env = "GTLBSTR_BACKUP_REPO_NAMING",
+        value_name = "NAMING"
+    )]
+    backup_repo_naming: Option<RepoNaming>,
+
+    /// Create missing bare repositories of local paths of the git backup provider
+    #[arg(long, env = "GTLBSTR_BACKUP_INIT_BARE")]
+    backup_init_bare: bool,
+
     /// Include regexp patterns (cannot be used together with --exclude flag, may be repeated)
     #[arg(short = 'i', long, env = "GTLBSTR_INCLUDE", value_name = "PATTERN")]
     include: Option<Vec<String>>,
@@ -84,7 +147,7 @@ struct Cli {
     /// Low-level option, how many projects can fetch in one request
     #[arg(
         long,
-        value_parser=clap::value_parser!(u32).range(1..101),
+        value_parser=clap::value_parser!(u32).range(OBJECTS_PER_PAGE),
         env = "GTLBSTR_OBJECTS_PER_PAGE",
         value_name = "COUNT"
     )]
@@ -94,15 +157,41 @@ struct Cli {
     #[arg(long, value_parser, env = "GTLBSTR_LIMIT", value_name = "COUNT")]
     limit: Option<usize>,
 
-    /// Limit concurrency download
+    /// Limit concurrency download [default: 21]
     #[arg(
         long,
-        value_parser,
+        value_parser=clap::value_parser!(u64).range(1..),
         env = "GTLBSTR_CONCURRENCY_LIMIT",
-        default_value_t = 21,
         value_name = "LIMIT"
     )]
-    concurrency_limit: usize,
+    concurrency_limit: Option<u64>,
+
+    /// Limit concurrent requests to each GitLab instance
+    #[arg(
+        long,
+        value_parser=clap::value_parser!(u64).range(1..),
+        env = "GTLBSTR_API_CONCURRENCY",
+        value_name = "LIMIT"
+    )]
+    api_concurrency: Option<u64>,
+
+    /// Limit concurrent git fetches [default: the concurrency limit]
+    #[arg(
+        long,
+        value_parser=clap::value_parser!(u64).range(1..),
+        env = "GTLBSTR_FETCH_CONCURRENCY",
+        value_name = "LIMIT"
+    )]
+    fetch_concurrency: Option<u64>,
+
+    /// Limit concurrent git pushes [default: the concurrency limit]
+    #[arg(
+        long,
+        value_parser=clap::value_parser!(u64).range(1..),
+        env = "GTLBSTR_PUSH_CONCURRENCY",
+        value_name = "LIMIT"
+    )]
+    push_concurrency: Option<u64>,
 
     /// Download projects explicitly owned by user
     #[arg(long, env = "GTLBSTR_ONLY_OWNED")]
@@ -112,7 +201,7 @@ struct Cli {
     #[arg(long, env = "GTLBSTR_ONLY_MEMBERSHIP")]
     only_membership: bool,
 
-    /// Download projects only in group
+    /// Download projects only in group (or an organization or a user on GitHub and Gitea)
     #[arg(long, env = "GTLBSTR_GROUP")]
     group: Option<String>,
 
@@ -160,6 +249,10 @@ struct Cli {
     #[arg(long, env = "GTLBSTR_GITLAB_TIMEOUT")]
     gitlab_timeout: Option<u32>,
 
+    /// Maximum requests per second to each GitLab instance
+    #[arg(long, env = "GTLBSTR_API_RATE", value_name = "REQUESTS")]
+    api_rate: Option<f64>,
+
     /// Continue downloading if a project cloning error occurs
     #[arg(long, env = "GTLBSTR_CONTINUE_ON_ERROR")]
     continue_on_error: bool,
@@ -167,92 +260,618 @@ struct Cli {
     /// Exclude archived projects
     #[arg(long, env = "GTLBSTR_EXCLUDE_ARCHIVED")]
     exclude_archived: bool,
+
+    /// Skip projects without activity since their last successful sync (saved in the dst folder)
+    #[arg(long, env = "GTLBSTR_INCREMENTAL")]
+    incremental: bool,
+
+    /// Keep bare mirrors of repositories (<path>.git) with all refs and push them to backups with deleting removed refs
+    #[arg(long, env = "GTLBSTR_MIRROR")]
+    mirror: bool,
+
+    /// Delete branches and tags removed in the source GitLab from local copies and backups. Deletions are listed with --dry-run
+    #[arg(long, env = "GTLBSTR_PRUNE")]
+    prune: bool,
+
+    /// Fetch and push Git LFS objects of projects with LFS enabled. Git LFS must be installed
+    #[arg(long, env = "GTLBSTR_LFS")]
+    lfs: bool,
+
+    /// Clone wikis of projects next to their repositories (<path>.wiki) and push them to wikis of backup projects
+    #[arg(long, env = "GTLBSTR_INCLUDE_WIKIS")]
+    include_wikis: bool,
+
+    /// Clone wikis of groups of projects into their folders and push them to wikis of backup groups (GitLab Premium)
+    #[arg(long, env = "GTLBSTR_INCLUDE_GROUP_WIKIS")]
+    include_group_wikis: bool,
+
+    /// Export labels, issues and merge requests with discussions of projects as JSON files into the .gitlobster/metadata folder of dst, only updated ones on next runs
+    #[arg(long, env = "GTLBSTR_EXPORT_METADATA")]
+    export_metadata: bool,
+
+    /// Sync projects by GitLab export archives (<path>.tar.gz) with issues, merge requests, wikis and settings instead of git. Archives are imported into backup instances, overwriting existing projects
+    #[arg(long, env = "GTLBSTR_EXPORT_MODE")]
+    export_mode: bool,
+
+    /// Maximum time of waiting for an export or an import of a project in seconds [default: 3600]
+    #[arg(
+        long,
+        value_parser=clap::value_parser!(u64).range(1..),
+        env = "GTLBSTR_EXPORT_TIMEOUT",
+        value_name = "SECONDS"
+    )]
+    export_timeout: Option<u64>,
+
+    /// Clone personal snippets and snippets of projects into the snippets folder of dst and recreate them in backups
+    #[arg(long, env = "GTLBSTR_INCLUDE_SNIPPETS")]
+    include_snippets: bool,
+
+    /// Save releases of projects with their asset files into the releases folder of dst, already downloaded files are skipped
+    #[arg(long, env = "GTLBSTR_INCLUDE_RELEASES")]
+    include_releases: bool,
+
+    /// Download files of generic packages of projects into the packages folder of dst, already downloaded files are skipped
+    #[arg(long, env = "GTLBSTR_INCLUDE_PACKAGES")]
+    include_packages: bool,
+
+    /// Write a bundle of every fetched repository into <path>.bundle of dst, verify it and record its checksum in bundles.json
+    #[arg(long, env = "GTLBSTR_BUNDLE")]
+    bundle: bool,
+
+    /// Write bundles with only objects missing in the previous bundle of the repository into <path>.<time>.bundle (implies --bundle)
+    #[arg(long, env = "GTLBSTR_BUNDLE_INCREMENTAL")]
+    bundle_incremental: bool,
+
+    /// Resume the last interrupted or failed run, skipping projects completed in it
+    #[arg(long, env = "GTLBSTR_RESUME")]
+    resume: bool,
+
+    /// Write a JSON report with results of all projects to the file
+    #[arg(long, env = "GTLBSTR_REPORT", value_name = "FILE")]
+    report: Option<String>,
+
+    /// Maximum attempts of failed GitLab requests and git commands with transient errors [default: 3]
+    #[arg(
+        long,
+        value_parser=clap::value_parser!(u32).range(1..),
+        env = "GTLBSTR_RETRY_ATTEMPTS",
+        value_name = "COUNT"
+    )]
+    retry_attempts: Option<u32>,
+
+    /// A delay before the first retry in seconds, doubled for each next one [default: 1]
+    #[arg(long, env = "GTLBSTR_RETRY_BACKOFF", value_name = "SECONDS")]
+    retry_backoff: Option<f64>,
 }
 
-pub fn run() -> Result<()> {
-    let cli = Cli::parse();
+/// Merge a boolean flag with a value from the config file
+fn flag(cli: bool, config: Option<bool>) -> bool {
+    cli || config.unwrap_or_default()
+}
 
-    let log_level = match cli.verbose {
-        0 => tracing::Level::ERROR,
-        1 => tracing::Level::WARN,
-        2 => tracing::Level::INFO,
-        3 => tracing::Level::DEBUG,
-        _ => tracing::Level::TRACE,
+/// Merge a concurrency limit with a value from the config file, zero is rejected like by the flag
+fn limit(name: &str, cli: Option<u64>, config: Option<usize>) -> Result<Option<usize>> {
+    let limit = cli.and_then(|l| usize::try_from(l).ok()).or(config);
+    if limit == Some(0) {
+        bail!("The {name} limit must be at least 1");
+    }
+    Ok(limit)
+}
+
+/// Concurrency limits merged with the config file
+struct Concurrency {
+    limit: usize,
+    api: Option<usize>,
+    fetch: Option<usize>,
+    push: Option<usize>,
+}
+
+impl Concurrency {
+    fn new(cli: &Cli, config: &Config) -> Result<Self> {
+        let limit_all = limit(
+            "concurrency",
+            cli.concurrency_limit,
+            config.concurrency_limit,
+        )?;
+        Ok(Self {
+            limit: limit_all.unwrap_or(DEFAULT_CONCURRENCY_LIMIT),
+            api: limit(
+                "API concurrency",
+                cli.api_concurrency,
+                config.api_concurrency,
+            )?,
+            fetch: limit(
+                "fetch concurrency",
+                cli.fetch_concurrency,
+                config.fetch_concurrency,
+            )?,
+            push: limit(
+                "push concurrency",
+                cli.push_concurrency,
+                config.push_concurrency,
+            )?,
+        })
+    }
+}
+
+/// Merge the page size and the request rate of APIs with the config file, config values
+/// are checked like the flags
+fn api_options(cli: &Cli, config: &Config) -> Result<(Option<u32>, Option<f64>)> {
+    let objects_per_page = cli.objects_per_page.or(config.objects_per_page);
+    if objects_per_page.is_some_and(|o| !OBJECTS_PER_PAGE.contains(&i64::from(o))) {
+        bail!("The objects per page must be between 1 and 100");
+    }
+
+    let api_rate = cli.api_rate.or(config.api_rate);
+    if api_rate.is_some_and(|r| r < MIN_API_RATE || !r.is_finite()) {
+        bail!("The --api-rate value must be a number of at least {MIN_API_RATE}");
+    }
+    Ok((objects_per_page, api_rate))
+}
+
+fn retry_policy(cli: &Cli, section: RetrySection) -> Result<retry::Policy> {
+    let mut policy = retry::Policy::default();
+    if let Some(attempts) = cli.retry_attempts.or(section.attempts) {
+        if attempts == 0 {
+            bail!("The retry attempts must be at least 1");
+        }
+        policy.attempts = attempts;
+    }
+    if let Some(backoff) = cli.retry_backoff.or(section.backoff) {
+        policy.backoff = Duration::try_from_secs_f64(backoff)?;
+    }
+    if let Some(max_backoff) = section.max_backoff {
+        policy.max_backoff = Duration::try_from_secs_f64(max_backoff)?;
+    }
+    if let Some(jitter) = section.jitter {
+        policy.jitter = jitter;
+    }
+    if let Some(statuses) = section.statuses {
+        policy.statuses = statuses;
+    }
+    if let Some(git_errors) = section.git_errors {
+        policy.git_errors = retry::Policy::git_errors(&git_errors)?;
+    }
+    Ok(policy)
+}
+
+fn fetch_options(cli: &Cli, sections: Vec<FetchSection>) -> Result<Vec<FetchOptions>> {
+    let cli_fetch = cli.fu.is_some() || cli.ft.is_some() || cli.group.is_some();
+    if cli_fetch && sections.len() > 1 {
+        bail!("The --fu, --ft and --group flags cannot be used with several fetch instances in a config file");
+    }
+
+    let mut sections = sections;
+    if sections.is_empty() {
+        sections.push(FetchSection::default());
+    }
+
+    let force_protocol = if cli.download_force_http {
+        Some(ForceProtocol::Http)
+    } else if cli.download_force_https {
+        Some(ForceProtocol::Https)
+    } else {
+        None
     };
-    tracing_subscriber::fmt().with_max_level(log_level).init();
 
-    let fetch_gl = FetchGitlabOptions::new(&cli.fu, &cli.ft)?;
+    let mut options = Vec::with_capacity(sections.len());
+    for section in sections {
+        let url = cli.fu.clone().or(section.url).ok_or_else(|| {
+            anyhow!("The fetch GitLab URL must be set by --fu or in a config file")
+        })?;
+        let token = cli.ft.clone().or(section.token).ok_or_else(|| {
+            anyhow!("The fetch GitLab token must be set by --ft or in a config file")
+        })?;
+
+        let mut fetch = FetchOptions::new(&url, &token)?;
+        fetch.provider = cli
+            .fetch_provider
+            .or(section.provider)
+            .unwrap_or(Provider::Gitlab);
+        fetch.group = cli.group.clone().or(section.group);
+        fetch.only_owned = flag(cli.only_owned, section.only_owned);
+        fetch.only_membership = flag(cli.only_membership, section.only_membership);
+        if fetch.only_membership && fetch.provider == Provider::Gitea {
+            bail!("The --only-membership flag is not supported by Gitea fetch instances");
+        }
+        fetch.exclude_archived = flag(cli.exclude_archived, section.exclude_archived);
+        fetch.download_ssh = flag(cli.download_ssh, section.ssh);
+        fetch.force_protocol = force_protocol
+            .clone()
+            .or(section.force_protocol)
+            .unwrap_or(ForceProtocol::No);
+        fetch.subdir = section.subdir;
+        fetch.backup_group = section.backup_group;
+        options.push(fetch);
+    }
+
+    Ok(options)
+}
 
-    let patterns = if cli.exclude.is_some() && cli.include.is_some() {
-        bail!("You cannot use the --include and --exclude flag together");
-    } else if let Some(patterns) = cli.exclude {
-        Some(FilterPatterns::Exclude(patterns))
+fn backup_options(cli: &Cli, sections: Vec<BackupSection>) -> Result<Vec<BackupOptions>> {
+    let cli_backup = cli.bu.is_some() || cli.bt.is_some() || cli.bg.is_some();
+    if cli_backup && sections.len() > 1 {
+        bail!("The --bu, --bt and --bg flags cannot be used with several backup targets in a config file");
+    }
+
+    let mut sections = sections;
+    if sections.is_empty() && cli_backup {
+        sections.push(BackupSection::default());
+    }
+
+    if cli.upload_force_http && cli.upload_force_https {
+        bail!("You cannot use --upload-force-http and --upload-force-https flags together");
+    }
+    let force_protocol = if cli.upload_force_http {
+        Some(ForceProtocol::Http)
+    } else if cli.upload_force_https {
+        Some(ForceProtocol::Https)
     } else {
-        cli.include.map(FilterPatterns::Include)
+        None
     };
 
     let upl_err = "For upload to another gitlab, you must specify both the --bt and --bu flags";
-    let backup_gl = if let (Some(url), Some(token)) = (&cli.bu, &cli.bt) {
-        Some(BackupGitlabOptions::new(url, token, cli.bg.clone())?)
-    } else {
-        if cli.bu.is_some() || cli.bt.is_some() {
+    let mut options = Vec::with_capacity(sections.len());
+    for section in sections {
+        let provider = cli
+            .backup_provider
+            .or(section.provider)
+            .unwrap_or(Provider::Gitlab);
+        // plain git targets have no API, so they need no token
+        let token = cli
+            .bt
+            .clone()
+            .or(section.token)
+            .or_else(|| (provider == Provider::Git).then(String::new));
+        let (Some(url), Some(token)) = (cli.bu.clone().or(section.url), token) else {
             bail!(upl_err);
         };
-        None
-    };
+        let mut backup = BackupOptions::new(&url, &token);
+        backup.provider = provider;
+        backup.group = cli.bg.clone().or(section.group);
+        backup.upload_ssh = flag(cli.upload_ssh, section.ssh);
+        backup.force_protocol = force_protocol
+            .clone()
+            .or(section.force_protocol)
+            .unwrap_or(ForceProtocol::No);
+        backup.disable_sync_date = flag(cli.disable_sync_date, section.disable_sync_date);
+        backup.visibility = cli.backup_visibility.or(section.visibility);
+        backup.repo_naming = cli
+            .backup_repo_naming
+            .or(section.repo_naming)
+            .unwrap_or(RepoNaming::Flatten);
+        backup.init_bare = flag(cli.backup_init_bare, section.init_bare);
+        if backup.repo_naming == RepoNaming::Organization {
+            if backup.provider != Provider::Gitea {
+                bail!(
+                    "The organization repository naming is supported only by Gitea backup targets"
+                );
+            }
+            if backup.group.is_some() {
+                bail!("You cannot use the organization repository naming with a backup group");
+            }
+        }
+        options.push(backup);
+    }
+
+    Ok(options)
+}
 
-    if backup_gl.is_none() && cli.bg.is_some() {
-        bail!(upl_err);
+fn filter_patterns(
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+) -> Result<Option<FilterPatterns>> {
+    if exclude.is_some() && include.is_some() {
+        bail!("You cannot use the --include and --exclude flag together");
     }
+    Ok(match exclude {
+        Some(patterns) => Some(FilterPatterns::Exclude(patterns)),
+        None => include.map(FilterPatterns::Include),
+    })
+}
+
+/// Merge flags with the config file and validate them
+fn clone_params(cli: Cli) -> Result<CloneParams> {
+    let config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
 
     if cli.download_force_http && cli.download_force_https {
         bail!("You cannot use --download-force-http and --download-force-https flags together");
     }
 
-    if cli.upload_force_http && cli.upload_force_https {
-        bail!("You cannot use --upload-force-http and --upload-force-https flags together");
+    let clear_dst = flag(cli.clear_dst, config.clear_dst);
+    if cli.resume && clear_dst {
+        bail!("You cannot use --resume and --clear-dst flags together");
     }
 
-    let download_force_protocol = if cli.download_force_http {
-        ForceProtocol::Http
-    } else if cli.download_force_https {
-        ForceProtocol::Https
-    } else {
-        ForceProtocol::No
-    };
+    let (objects_per_page, api_rate) = api_options(&cli, &config)?;
+    let concurrency = Concurrency::new(&cli, &config)?;
+    retry::init(retry_policy(&cli, config.retry)?);
 
-    let upload_force_protocol = if cli.upload_force_http {
-        ForceProtocol::Http
-    } else if cli.upload_force_https {
-        ForceProtocol::Https
+    let only_master = flag(cli.only_master, config.only_master);
+    let mirror = flag(cli.mirror, config.mirror);
+    if mirror && only_master {
+        bail!("You cannot use --mirror and --only-master flags together");
+    }
+
+    // only the default branch is fetched, so pruning would delete all other refs of backups
+    let prune = flag(cli.prune, config.prune);
+    if prune && only_master {
+        bail!("You cannot use --prune and --only-master flags together");
+    }
+
+    let lfs = flag(cli.lfs, config.lfs);
+    let include_wikis = flag(cli.include_wikis, config.include_wikis);
+    let include_group_wikis = flag(cli.include_group_wikis, config.include_group_wikis);
+    let export_mode = flag(cli.export_mode, config.export_mode);
+    let bundle_incremental = flag(cli.bundle_incremental, config.bundle_incremental);
+    let bundle = flag(cli.bundle, config.bundle) || bundle_incremental;
+    if export_mode && (mirror || prune || lfs || include_wikis || include_group_wikis || bundle) {
+        bail!("You cannot use --export-mode with --mirror, --prune, --lfs, --include-wikis, --include-group-wikis or --bundle flags");
+    }
+
+    let export_timeout = cli
+        .export_timeout
+        .or(config.export_timeout)
+        .unwrap_or(DEFAULT_EXPORT_TIMEOUT);
+    if export_timeout == 0 {
+        bail!("The export timeout must be at least 1 second");
+    }
+
+    let fetch_gl = fetch_options(&cli, config.fetch)?;
+    let backup_gl = backup_options(&cli, config.backup)?;
+    if export_mode
+        && (fetch_gl.iter().any(|f| f.provider != Provider::Gitlab)
+            || backup_gl.iter().any(|b| b.provider != Provider::Gitlab))
+    {
+        bail!("You cannot use --export-mode with GitHub, Gitea or plain git instances");
+    }
+
+    let patterns = if cli.include.is_some() || cli.exclude.is_some() {
+        filter_patterns(cli.include, cli.exclude)?
     } else {
-        ForceProtocol::No
+        filter_patterns(config.include, config.exclude)?
     };
 
-    let clone_params = CloneParams {
+    Ok(CloneParams {
         fetch: fetch_gl,
-        dst: cli.dst,
+        dst: cli.dst.or(config.dst),
         backup: backup_gl,
         patterns,
         dry_run: cli.dry_run,
-        group: cli.group,
-        objects_per_page: cli.objects_per_page,
-        limit: cli.limit,
-        concurrency_limit: cli.concurrency_limit,
-        only_owned: cli.only_owned,
-        only_membership: cli.only_membership,
-        download_ssh: cli.download_ssh,
-        upload_ssh: cli.upload_ssh,
-        disable_hierarchy: cli.disable_hierarchy,
-        clear_dst: cli.clear_dst,
-        only_master: cli.only_master,
-        disable_sync_date: cli.disable_sync_date,
-        gitlab_timeout: cli.gitlab_timeout,
-        download_force_protocol,
-        upload_force_protocol,
-        continue_on_error: cli.continue_on_error,
-        exclude_archived: cli.exclude_archived,
+        objects_per_page,
+        limit: cli.limit.or(config.limit),
+        concurrency_limit: concurrency.limit,
+        api_concurrency: concurrency.api,
+        fetch_concurrency: concurrency.fetch,
+        push_concurrency: concurrency.push,
+        disable_hierarchy: flag(cli.disable_hierarchy, config.disable_hierarchy),
+        clear_dst,
+        only_master,
+        gitlab_timeout: cli.gitlab_timeout.or(config.gitlab_timeout),
+        api_rate,
+        continue_on_error: flag(cli.continue_on_error, config.continue_on_error),
+        incremental: flag(cli.incremental, config.incremental),
+        mirror,
+        prune,
+        lfs,
+        include_wikis,
+        include_group_wikis,
+        export_metadata: flag(cli.export_metadata, config.export_metadata),
+        export_mode,
+        export_timeout: Duration::from_secs(export_timeout),
+        include_snippets: flag(cli.include_snippets, config.include_snippets),
+        include_releases: flag(cli.include_releases, config.include_releases),
+        include_packages: flag(cli.include_packages, config.include_packages),
+        bundle,
+        bundle_incremental,
+        resume: cli.resume,
+        report: cli.report.or(config.report),
+    })
+}
+
+pub fn run() -> Result<()> {
+    let cli = Cli::parse();
+
+    let log_level = match cli.verbose {
+        0 => tracing::Level::ERROR,
+        1 => tracing::Level::WARN,
+        2 => tracing::Level::INFO,
+        3 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
     };
+    tracing_subscriber::fmt().with_max_level(log_level).init();
+
+    // invalid values exit with the same code as invalid flags rejected by clap
+    let clone_params = clone_params(cli).context(Failure::Usage)?;
 
     clone(clone_params)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from([&["gitlobster"], args].concat()).unwrap()
+    }
+
+    #[test]
+    fn merge_flags() {
+        assert!(flag(true, None));
+        assert!(flag(true, Some(false)));
+        assert!(flag(false, Some(true)));
+        assert!(!flag(false, None));
+
+        assert_eq!(limit("push", Some(3), Some(5)).unwrap(), Some(3));
+        assert_eq!(limit("push", None, Some(5)).unwrap(), Some(5));
+        assert_eq!(limit("push", None, None).unwrap(), None);
+        let err = limit("push", None, Some(0)).err().unwrap();
+        assert_eq!(err.to_string(), "The push limit must be at least 1");
+    }
+
+    #[test]
+    fn zero_limits() {
+        for flag in [
+            "--concurrency-limit",
+            "--api-concurrency",
+            "--fetch-concurrency",
+            "--push-concurrency",
+        ] {
+            assert!(Cli::try_parse_from(["gitlobster", flag, "0"]).is_err());
+        }
+    }
+
+    /// The error of flags merged with a config file, the file is a TOML one named `name`
+    fn config_error(name: &str, content: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("gitlobster-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        let path = path.to_string_lossy();
+        let cli = cli(&[
+            "--fu",
+            "https://gitlab.com",
+            "--ft",
+            "token",
+            "--config",
+            &path,
+        ]);
+        let err = clone_params(cli).err().unwrap();
+        format!("{err:#}")
+    }
+
+    #[test]
+    fn config_objects_per_page() {
+        for value in ["0", "101"] {
+            let err = config_error("objects.toml", &format!("objects_per_page = {value}"));
+            assert!(err.contains("between 1 and 100"), "{err}");
+        }
+    }
+
+    #[test]
+    fn zero_limits_in_config() {
+        for key in [
+            "concurrency_limit",
+            "api_concurrency",
+            "fetch_concurrency",
+            "push_concurrency",
+        ] {
+            let err = config_error(&format!("{key}.toml"), &format!("{key} = 0"));
+            assert!(err.contains("must be at least 1"), "{err}");
+        }
+    }
+
+    #[test]
+    fn zero_retry_attempts() {
+        let err = config_error("attempts.toml", "[retry]\nattempts = 0");
+        assert!(err.contains("retry attempts must be at least 1"), "{err}");
+        assert!(Cli::try_parse_from(["gitlobster", "--retry-attempts", "0"]).is_err());
+    }
+
+    #[test]
+    fn tiny_api_rate() {
+        let err = config_error("rate.toml", "api_rate = 1e-30");
+        assert!(err.contains("--api-rate"), "{err}");
+        let cli = cli(&[
+            "--fu",
+            "https://gitlab.com",
+            "--ft",
+            "token",
+            "--api-rate",
+            "0",
+        ]);
+        assert!(clone_params(cli).is_err());
+    }
+
+    #[test]
+    fn prune_with_only_master() {
+        let cli = cli(&[
+            "--fu",
+            "https://gitlab.com",
+            "--ft",
+            "token",
+            "--prune",
+            "--only-master",
+        ]);
+        let err = clone_params(cli).err().unwrap();
+        assert!(
+            err.to_string().contains("--prune and --only-master"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn fetch_flags_override_config() {
+        let mut section = FetchSection::default();
+        section.url = Some("https://config.example.com".to_string());
+        section.token = Some("config".to_string());
+        section.group = Some("config-group".to_string());
+        section.subdir = Some("config".to_string());
+        section.ssh = Some(true);
+
+        let options = fetch_options(
+            &cli(&["--ft", "cli", "--group", "cli-group", "--only-owned"]),
+            vec![section],
+        )
+        .unwrap();
+
+        assert_eq!(options.len(), 1);
+        let fetch = &options[0];
+        assert_eq!(fetch.group.as_deref(), Some("cli-group"));
+        assert_eq!(fetch.subdir.as_deref(), Some("config"));
+        assert!(fetch.only_owned);
+        assert!(fetch.download_ssh);
+        assert_eq!(fetch.provider, Provider::Gitlab);
+    }
+
+    #[test]
+    fn fetch_flags_with_several_instances() {
+        let sections = vec![FetchSection::default(), FetchSection::default()];
+        assert!(fetch_options(&cli(&["--fu", "https://gitlab.com"]), sections).is_err());
+    }
+
+    #[test]
+    fn fetch_url_is_required() {
+        assert!(fetch_options(&cli(&["--ft", "token"]), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn only_membership_with_gitea() {
+        let section = || {
+            let mut section = FetchSection::default();
+            section.provider = Some(Provider::Gitea);
+            section.url = Some("https://gitea.example.com".to_string());
+            section.token = Some("token".to_string());
+            section
+        };
+
+        let options = fetch_options(&cli(&[]), vec![section()]).unwrap();
+        assert!(!options[0].only_membership);
+        assert!(fetch_options(&cli(&["--only-membership"]), vec![section()]).is_err());
+    }
+
+    #[test]
+    fn backup_flags_override_config() {
+        let mut section = BackupSection::default();
+        section.provider = Some(Provider::Gitea);
+        section.url = Some("https://gitea.example.com".to_string());
+        section.token = Some("config".to_string());
+        section.group = Some("config-group".to_string());
+
+        let options = backup_options(&cli(&["--bg", "cli-group"]), vec![section]).unwrap();
+
+        assert_eq!(options.len(), 1);
+        let backup = &options[0];
+        assert_eq!(backup.provider, Provider::Gitea);
+        assert_eq!(backup.group.as_deref(), Some("cli-group"));
+        assert_eq!(backup.repo_naming, RepoNaming::Flatten);
+    }
+
+    #[test]
+    fn backup_is_optional() {
+        assert!(backup_options(&cli(&[]), Vec::new()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::cloner::{ForceProtocol, Provider, RepoNaming};
+use crate::gitlab::types::Visibility;
+
+/// Unknown keys collected while parsing a section of the config file
+type UnknownKeys = BTreeMap<String, serde_json::Value>;
+
+/// Settings of a GitLab, GitHub or Gitea instance to fetch repositories from
+#[derive(Deserialize, Default)]
+pub struct FetchSection {
+    pub provider: Option<Provider>,
+    pub url: Option<String>,
+    pub token: Option<String>,
+    pub subdir: Option<String>,
+    pub backup_group: Option<String>,
+    pub group: Option<String>,
+    pub only_owned: Option<bool>,
+    pub only_membership: Option<bool>,
+    pub exclude_archived: Option<bool>,
+    pub ssh: Option<bool>,
+    pub force_protocol: Option<ForceProtocol>,
+    #[serde(flatten)]
+    unknown: UnknownKeys,
+}
+
+/// Settings of a GitLab, GitHub or Gitea instance or a plain git server to push repositories to
+#[derive(Deserialize, Default)]
+pub struct BackupSection {
+    pub provider: Option<Provider>,
+    pub url: Option<String>,
+    pub token: Option<String>,
+    pub group: Option<String>,
+    pub ssh: Option<bool>,
+    pub force_protocol: Option<ForceProtocol>,
+    pub disable_sync_date: Option<bool>,
+    /// Visibility of backup projects instead of the visibility of source projects
+    pub visibility: Option<Visibility>,
+    pub repo_naming: Option<RepoNaming>,
+    pub init_bare: Option<bool>,
+    #[serde(flatten)]
+    unknown: UnknownKeys,
+}
+
+/// Settings of retries of failed requests and git commands
+#[derive(Deserialize, Default)]
+pub struct RetrySection {
+    pub attempts: Option<u32>,
+    /// A delay before the first retry in seconds
+    pub backoff: Option<f64>,
+    /// A maximum delay between retries in seconds
+    pub max_backoff: Option<f64>,
+    pub jitter: Option<bool>,
+    /// Retryable HTTP statuses of GitLab responses
+    pub statuses: Option<Vec<u16>>,
+    /// Regexp patterns of retryable git errors
+    pub git_errors: Option<Vec<String>>,
+    #[serde(flatten)]
+    unknown: UnknownKeys,
+}
+
+/// A declarative alternative to the command line flags
+#[derive(Deserialize, Default)]
+pub struct Config {
+    #[serde(default)]
+    pub fetch: Vec<FetchSection>,
+    #[serde(default)]
+    pub backup: Vec<BackupSection>,
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    pub dst: Option<String>,
+    pub objects_per_page: Option<u32>,
+    pub limit: Option<usize>,
+    pub concurrency_limit: Option<usize>,
+    pub api_concurrency: Option<usize>,
+    pub fetch_concurrency: Option<usize>,
+    pub push_concurrency: Option<usize>,
+    pub disable_hierarchy: Option<bool>,
+    pub clear_dst: Option<bool>,
+    pub only_master: Option<bool>,
+    pub gitlab_timeout: Option<u32>,
+    pub api_rate: Option<f64>,
+    pub continue_on_error: Option<bool>,
+    pub incremental: Option<bool>,
+    pub mirror: Option<bool>,
+    pub prune: Option<bool>,
+    pub lfs: Option<bool>,
+    pub include_wikis: Option<bool>,
+    pub include_group_wikis: Option<bool>,
+    pub export_metadata: Option<bool>,
+    pub export_mode: Option<bool>,
+    pub export_timeout: Option<u64>,
+    pub include_snippets: Option<bool>,
+    pub include_releases: Option<bool>,
+    pub include_packages: Option<bool>,
+    pub bundle: Option<bool>,
+    pub bundle_incremental: Option<bool>,
+    pub report: Option<String>,
+    #[serde(default)]
+    pub retry: RetrySection,
+    #[serde(flatten)]
+    unknown: UnknownKeys,
+}
+
+impl Config {
+    /// Load a config file, the format is chosen by the file extension (toml, yaml or yml)
+    pub fn load(path: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the config file {path}"))?;
+
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        let parse_err = || format!("Failed to parse the config file {path}");
+        let config: Config = match extension.as_str() {
+            "toml" => toml::from_str(&content).with_context(parse_err)?,
+            "yaml" | "yml" => serde_yaml::from_str(&content).with_context(parse_err)?,
+            _ => bail!("Unsupported config file format of {path} (expected .toml, .yaml or .yml)"),
+        };
+
+        config.check_unknown_keys(path)?;
+
+        Ok(config)
+    }
+
+    fn check_unknown_keys(&self, path: &str) -> Result<()> {
+        let mut unknown: Vec<String> = self.unknown.keys().cloned().collect();
+        for (i, fetch) in self.fetch.iter().enumerate() {
+            unknown.extend(fetch.unknown.keys().map(|k| format!("fetch[{i}].{k}")));
+        }
+        for (i, backup) in self.backup.iter().enumerate() {
+            unknown.extend(backup.unknown.keys().map(|k| format!("backup[{i}].{k}")));
+        }
+        unknown.extend(self.retry.unknown.keys().map(|k| format!("retry.{k}")));
+
+        if !unknown.is_empty() {
+            bail!(
+                "Unknown keys in the config file {path}: {}",
+                unknown.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn try_load(name: &str, content: &str) -> Result<Config> {
+        let dir = std::env::temp_dir().join(format!("gitlobster-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        Config::load(path.to_str().unwrap())
+    }
+
+    fn load(name: &str, content: &str) -> Config {
+        try_load(name, content).unwrap_or_else(|e| panic!("{e:#}"))
+    }
+
+    #[test]
+    fn load_toml() {
+        let config = load(
+            "load.toml",
+            r#"
+            dst = "/backup"
+            only_master = true
+            concurrency_limit = 4
+
+            [[fetch]]
+            url = "https://gitlab.com"
+            token = "secret"
+            group = "team"
+
+            [[backup]]
+            provider = "gitea"
+            url = "https://gitea.example.com"
+            repo_naming = "organization"
+
+            [retry]
+            attempts = 5
+            statuses = [502, 503]
+            "#,
+        );
+
+        assert_eq!(config.dst.as_deref(), Some("/backup"));
+        assert_eq!(config.only_master, Some(true));
+        assert_eq!(config.concurrency_limit, Some(4));
+        assert_eq!(config.fetch.len(), 1);
+        assert_eq!(config.fetch[0].group.as_deref(), Some("team"));
+        assert_eq!(config.backup[0].provider, Some(Provider::Gitea));
+        assert_eq!(config.backup[0].repo_naming, Some(RepoNaming::Organization));
+        assert_eq!(config.retry.attempts, Some(5));
+        assert_eq!(config.retry.statuses, Some(vec![502, 503]));
+    }
+
+    #[test]
+    fn load_yaml() {
+        let config = load(
+            "load.yml",
+            "
+fetch:
+  - url: https://gitlab.com
+    token: first
+  - url: https://gitlab.example.com
+    token: second
+    subdir: example
+include:
+  - ^team/
+",
+        );
+
+        assert_eq!(config.fetch.len(), 2);
+        assert_eq!(config.fetch[1].subdir.as_deref(), Some("example"));
+        assert_eq!(config.include, Some(vec!["^team/".to_string()]));
+        assert!(config.backup.is_empty());
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let Err(err) = try_load(
+            "unknown.toml",
+            r#"
+            dts = "/backup"
+
+            [[fetch]]
+            url = "https://gitlab.com"
+            tokn = "secret"
+
+            [retry]
+            atempts = 5
+            "#,
+        ) else {
+            panic!("unknown keys are accepted");
+        };
+
+        let err = err.to_string();
+        assert!(err.contains("dts"), "{err}");
+        assert!(err.contains("fetch[0].tokn"), "{err}");
+        assert!(err.contains("retry.atempts"), "{err}");
+    }
+
+    #[test]
+    fn unsupported_format() {
+        assert!(try_load("config.json", "{}").is_err());
+    }
+}
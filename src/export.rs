@@ -0,0 +1,87 @@
+use anyhow::{bail, Result};
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::gitlab::{self, types};
+
+/// A delay between checks of export and import statuses
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Check the status until `check` reports completion or the timeout expires
+async fn wait_for<F, Fut>(what: &str, timeout: Duration, mut check: F) -> Result<()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<bool>>,
+{
+    let deadline = Instant::now() + timeout;
+    while !check().await? {
+        if Instant::now() >= deadline {
+            bail!("{what} is not finished in {}s", timeout.as_secs());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    Ok(())
+}
+
+/// Export the project by GitLab and download the archive, returns the archive size
+pub async fn download(
+    client: &gitlab::Client,
+    project: &types::Project,
+    archive: &Path,
+    timeout: Duration,
+) -> Result<u64> {
+    client.schedule_export(project.id).await?;
+    wait_for(
+        &format!("export of {}", project.path_with_namespace),
+        timeout,
+        || async {
+            let status = client.get_export_status(project.id).await?;
+            match status.export_status.as_str() {
+                "finished" => Ok(true),
+                "failed" => bail!("export of {} failed", project.path_with_namespace),
+                _ => Ok(false),
+            }
+        },
+    )
+    .await?;
+
+    let size = client
+        .download(&format!("projects/{}/export/download", project.id), archive)
+        .await?
+        .size;
+    info!(
+        "export of {} downloaded to {}",
+        project.path_with_namespace,
+        archive.display()
+    );
+    Ok(size)
+}
+
+/// Import the archive as the project `path` into the namespace, an existing project is overwritten
+pub async fn import(
+    client: &gitlab::Client,
+    archive: &Path,
+    namespace: Option<u32>,
+    path: &str,
+    name: &str,
+    timeout: Duration,
+) -> Result<()> {
+    let imported = client
+        .import_project(archive, namespace, path, name)
+        .await?;
+    wait_for(&format!("import of {path}"), timeout, || async {
+        let status = client.get_import_status(imported.id).await?;
+        match status.import_status.as_str() {
+            "finished" => Ok(true),
+            "failed" => bail!(
+                "import of {path} failed: {}",
+                status.import_error.unwrap_or_default()
+            ),
+            _ => Ok(false),
+        }
+    })
+    .await
+}
@@ -1,9 +1,19 @@
 use anyhow::{bail, Result};
+use regex::Regex;
+use std::collections::{BTreeMap, HashSet};
 use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 use std::str::from_utf8;
+use std::sync::LazyLock;
 use tokio::process::Command;
 use tracing::{error, info, warn};
 
+use crate::retry;
+
+/// Credentials in URLs of remotes
+static CREDENTIALS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"://[^/@\s]+@").expect("invalid credentials pattern"));
+
 async fn git<S: AsRef<OsStr>>(args: Vec<S>) -> Result<String> {
     let mut git_cmd = "git".to_string();
     for a in &args {
@@ -11,6 +21,13 @@ async fn git<S: AsRef<OsStr>>(args: Vec<S>) -> Result<String> {
     }
     info!("{}", git_cmd);
 
+    // retries are logged with a lower verbosity, so credentials are hidden there
+    let name = CREDENTIALS.replace_all(&git_cmd, "://***@");
+
+    retry::run(&name, || run(&args), retry::Policy::is_retryable_git).await
+}
+
+async fn run<S: AsRef<OsStr>>(args: &[S]) -> Result<String> {
     let cmd = Command::new("git").args(args).output().await?;
 
     let errmsg = from_utf8(&cmd.stderr).unwrap_or_default();
@@ -39,7 +56,7 @@ async fn clone(src: &String, dst: &String) -> Result<()> {
     Ok(())
 }
 
-async fn update(path: &String, only_master: bool) -> Result<()> {
+async fn update(path: &String, only_master: bool, prune: bool) -> Result<()> {
     // In older versions of GitLobster, we used to rename the "origin" remote to "upstream".
     // For backward compatibility, we should revert the default remote name back to "origin",
     // while we don't increase the major version number.
@@ -47,12 +64,28 @@ async fn update(path: &String, only_master: bool) -> Result<()> {
         .await
         .ok();
 
+    // local branches are re-created from remote ones below, so stale branches are removed too.
+    // Only origin is fetched, otherwise stale tags would be fetched back from the backup remote
+    if prune {
+        git(vec![
+            "-C",
+            path,
+            "fetch",
+            "--prune",
+            "--prune-tags",
+            "origin",
+        ])
+        .await?;
+    }
+
     if only_master {
         git(vec!["-C", path, "pull"]).await?;
         return Ok(());
     }
 
-    git(vec!["-C", path, "fetch", "--all"]).await?;
+    if !prune {
+        git(vec!["-C", path, "fetch", "--all"]).await?;
+    }
 
     let branches_out = git(vec!["-C", path, "branch", "-la"]).await?;
     let branches = branches_out
@@ -102,6 +135,28 @@ async fn add_remote_backup(path: &String, remote: String) -> Result<()> {
     Ok(())
 }
 
+/// Push branches and tags, deleting ones missing in the local copy from the backup
+async fn push_pruning_remote_backup(path: String) -> Result<()> {
+    let out = git(vec![
+        "-C",
+        &path,
+        "push",
+        "--porcelain",
+        "--prune",
+        "backup",
+        "+refs/heads/*:refs/heads/*",
+        "+refs/tags/*:refs/tags/*",
+    ])
+    .await?;
+    // deleted refs are reported as "-\t:<ref>\t[deleted]"
+    for line in out.lines().filter(|l| l.starts_with('-')) {
+        if let Some(r) = line.split('\t').nth(1) {
+            info!("deleted {} from backup", r.trim_start_matches(':'));
+        }
+    }
+    Ok(())
+}
+
 async fn push_all_remote_backup(path: String) -> Result<()> {
     if let Err(e) = git(vec!["-C", &path, "push", "-u", "backup", "--all"]).await {
         error!("{}", e);
@@ -112,15 +167,507 @@ async fn push_all_remote_backup(path: String) -> Result<()> {
     Ok(())
 }
 
-pub async fn fetch(src: String, dst: String, only_master: bool) -> Result<()> {
-    match check_status(&dst).await {
-        Ok(()) => (),
-        Err(_) => clone(&src, &dst).await?,
+/// Clone or update a repository, returns true if the repository was cloned
+pub async fn fetch(src: String, dst: String, only_master: bool, prune: bool) -> Result<bool> {
+    let cloned = check_status(&dst).await.is_err();
+    if cloned {
+        clone(&src, &dst).await?;
+    }
+    update(&dst, only_master, prune).await?;
+    Ok(cloned)
+}
+
+/// Clone or update a bare mirror of a repository, returns true if the mirror was cloned
+pub async fn fetch_mirror(src: String, dst: String) -> Result<bool> {
+    let is_bare = git(vec!["-C", &dst, "rev-parse", "--is-bare-repository"])
+        .await
+        .is_ok_and(|out| out.trim() == "true");
+    if !is_bare {
+        git(vec!["clone", "--mirror", &src, &dst]).await?;
+        return Ok(true);
+    }
+
+    // credentials in the source URL may be changed since the last run
+    git(vec!["-C", &dst, "remote", "set-url", "origin", &src]).await?;
+    git(vec!["-C", &dst, "remote", "update", "--prune"]).await?;
+    Ok(false)
+}
+
+/// Size of files of a repository in bytes, the repository is walked on a blocking thread
+pub async fn repo_size(path: &str) -> u64 {
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || dir_size(&git_dir(&path)))
+        .await
+        .unwrap_or_default()
+}
+
+/// Size of LFS objects of a repository in bytes
+pub async fn lfs_size(path: &str) -> u64 {
+    let path = path.to_string();
+    tokio::task::spawn_blocking(move || dir_size(&git_dir(&path).join("lfs").join("objects")))
+        .await
+        .unwrap_or_default()
+}
+
+/// The git directory of a working copy or a bare repository
+fn git_dir(path: &str) -> PathBuf {
+    let git_dir = Path::new(path).join(".git");
+    if git_dir.is_dir() {
+        git_dir
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
     };
-    update(&dst, only_master).await
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
 }
 
-pub async fn push_backup(path: String, remote: String) -> Result<()> {
+/// Whether any commit of the repository has LFS files, by the LFS filter in `.gitattributes`
+pub async fn uses_lfs(path: &str) -> Result<bool> {
+    let out = git(vec![
+        "-C",
+        path,
+        "log",
+        "--all",
+        "-1",
+        "--format=%H",
+        "-G",
+        "filter=lfs",
+        "--",
+        ":(glob)**/.gitattributes",
+    ])
+    .await?;
+    Ok(!out.trim().is_empty())
+}
+
+/// Fetch LFS objects of all refs of the origin remote
+pub async fn lfs_fetch(path: &str) -> Result<()> {
+    git(vec!["-C", path, "lfs", "fetch", "--all", "origin"]).await?;
+    Ok(())
+}
+
+async fn lfs_push(path: &str, remote: &str) -> Result<()> {
+    git(vec!["-C", path, "lfs", "push", "--all", remote]).await?;
+    Ok(())
+}
+
+/// Push a repository to the backup, LFS objects are pushed first if `lfs` is set,
+/// so the backup never gets LFS pointers without their objects
+pub async fn push_backup(path: String, remote: String, prune: bool, lfs: bool) -> Result<()> {
     add_remote_backup(&path, remote).await?;
-    push_all_remote_backup(path).await
+    if lfs {
+        lfs_push(&path, "backup").await?;
+    }
+    if prune {
+        push_pruning_remote_backup(path).await
+    } else {
+        push_all_remote_backup(path).await
+    }
+}
+
+async fn remote_refs(path: &str, remote: &str) -> Result<HashSet<String>> {
+    let out = git(vec![
+        "-C",
+        path,
+        "ls-remote",
+        "--heads",
+        "--tags",
+        "--refs",
+        remote,
+    ])
+    .await?;
+    Ok(out
+        .lines()
+        .filter_map(|l| l.split('\t').nth(1))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Branches and tags of the target (a remote or the local repository if it is not set)
+/// which are missing in the source remote
+pub async fn stale_refs(path: &str, source: &str, target: Option<&str>) -> Result<Vec<String>> {
+    let source_refs = remote_refs(path, source).await?;
+    let target_refs: HashSet<String> = match target {
+        Some(target) => remote_refs(path, target).await?,
+        None => git(vec![
+            "-C",
+            path,
+            "for-each-ref",
+            "--format=%(refname)",
+            "refs/heads",
+            "refs/tags",
+        ])
+        .await?
+        .lines()
+        .map(str::to_string)
+        .collect(),
+    };
+    let mut stale: Vec<String> = target_refs.difference(&source_refs).cloned().collect();
+    stale.sort();
+    Ok(stale)
+}
+
+/// Object ids of branches, tags and notes of the repository by their names,
+/// remote-tracking refs of working copies are left out, they change with every push
+pub async fn refs(path: &str) -> Result<BTreeMap<String, String>> {
+    let out = git(vec![
+        "-C",
+        path,
+        "for-each-ref",
+        "--format=%(refname) %(objectname)",
+        "refs/heads",
+        "refs/tags",
+        "refs/notes",
+    ])
+    .await?;
+    Ok(out
+        .lines()
+        .filter_map(|l| l.split_once(' '))
+        .map(|(name, id)| (name.to_string(), id.to_string()))
+        .collect())
+}
+
+/// Write a bundle of branches, tags and notes without objects reachable from `exclude`,
+/// returns false if there is nothing new to bundle
+pub async fn bundle_create(path: &str, file: &Path, exclude: &[String]) -> Result<bool> {
+    let file = file.to_string_lossy();
+    let mut args = vec![
+        "-C",
+        path,
+        "bundle",
+        "create",
+        &file,
+        "--branches",
+        "--tags",
+        "--glob=refs/notes/*",
+    ];
+    let exclude: Vec<String> = exclude.iter().map(|id| format!("^{id}")).collect();
+    args.extend(exclude.iter().map(String::as_str));
+    match git(args).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.to_string().contains("Refusing to create empty bundle") => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether all objects are in the repository, objects of rewritten history are lost after gc
+pub async fn has_objects(path: &str, ids: &[String]) -> Result<bool> {
+    for id in ids {
+        let status = Command::new("git")
+            .args(["-C", path, "cat-file", "-e", id])
+            .status()
+            .await?;
+        if !status.success() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Check that the bundle is valid and its prerequisites are in the repository
+pub async fn bundle_verify(path: &str, file: &Path) -> Result<()> {
+    let file = file.to_string_lossy();
+    git(vec!["-C", path, "bundle", "verify", "--quiet", &file]).await?;
+    Ok(())
+}
+
+/// Create a bare repository with HEAD pointing to `default_branch` if it is set
+pub async fn init_bare(path: &str, default_branch: Option<&str>) -> Result<()> {
+    git(vec!["init", "--bare", "--quiet", path]).await?;
+    if let Some(branch) = default_branch {
+        git(vec![
+            "-C",
+            path,
+            "symbolic-ref",
+            "HEAD",
+            &format!("refs/heads/{branch}"),
+        ])
+        .await?;
+    }
+    Ok(())
+}
+
+/// Push the current branch of a snippet repository to the default branch of the backup snippet,
+/// snippet repositories accept only their default branch
+pub async fn push_snippet(path: &str, remote: &str) -> Result<()> {
+    // the default branch is reported as "ref: refs/heads/<branch>\tHEAD"
+    let remote_head = git(vec!["-C", path, "ls-remote", "--symref", remote, "HEAD"]).await?;
+    let branch = match remote_head
+        .lines()
+        .find_map(|l| l.strip_prefix("ref: ")?.split('\t').next())
+    {
+        Some(branch) => branch.to_string(),
+        None => git(vec!["-C", path, "symbolic-ref", "HEAD"])
+            .await?
+            .trim()
+            .to_string(),
+    };
+    git(vec![
+        "-C",
+        path,
+        "push",
+        "--force",
+        remote,
+        &format!("HEAD:{branch}"),
+    ])
+    .await?;
+    Ok(())
+}
+
+/// Push branches, tags and notes of a mirror, removing refs missing in the mirror from the backup.
+/// GitLab rejects pushes of its own refs (merge requests, pipelines), so they are kept only locally
+pub async fn push_mirror(path: String, remote: String, lfs: bool) -> Result<()> {
+    if lfs {
+        lfs_push(&path, &remote).await?;
+    }
+    // the backup is not added as a remote, otherwise `remote update` would fetch it into the mirror
+    git(vec![
+        "-C",
+        &path,
+        "push",
+        "--prune",
+        &remote,
+        "+refs/heads/*:refs/heads/*",
+        "+refs/tags/*:refs/tags/*",
+        "+refs/notes/*:refs/notes/*",
+    ])
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A temporary directory removed at the end of a test
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("gitlobster-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        pub fn path(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Run git synchronously in the working copy for preparing repositories
+    pub fn run_git(path: &str, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args([
+                "-C",
+                path,
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@localhost",
+            ])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(status.status.success(), "git {args:?}: {status:?}");
+    }
+
+    /// A bare repository with the main and feature branches and a tag, and its working copy
+    pub fn source_repo(dir: &TempDir) -> (String, String) {
+        let (bare, work) = (dir.path("source.git"), dir.path("source"));
+        run_git(".", &["init", "--quiet", "--bare", "-b", "main", &bare]);
+        run_git(".", &["clone", "--quiet", &bare, &work]);
+        run_git(
+            &work,
+            &["commit", "--quiet", "--allow-empty", "-m", "first"],
+        );
+        run_git(&work, &["tag", "v1"]);
+        run_git(&work, &["branch", "feature"]);
+        run_git(
+            &work,
+            &["push", "--quiet", "origin", "main", "feature", "v1"],
+        );
+        (bare, work)
+    }
+
+    async fn backup_refs(path: &str, backup: &str) -> Vec<String> {
+        let mut refs: Vec<String> = remote_refs(path, backup)
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+        refs.sort();
+        refs
+    }
+
+    #[tokio::test]
+    async fn prune_keeps_existing_branches() {
+        let dir = TempDir::new("prune");
+        let (source, work) = source_repo(&dir);
+        let (local, backup) = (dir.path("local"), dir.path("backup.git"));
+        init_bare(&backup, Some("main")).await.unwrap();
+
+        fetch(source.clone(), local.clone(), false, true)
+            .await
+            .unwrap();
+        push_backup(local.clone(), backup.clone(), true, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            backup_refs(&local, "backup").await,
+            ["refs/heads/feature", "refs/heads/main", "refs/tags/v1"]
+        );
+
+        // a run without changes in the source keeps all refs of the backup
+        fetch(source.clone(), local.clone(), false, true)
+            .await
+            .unwrap();
+        push_backup(local.clone(), backup.clone(), true, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            backup_refs(&local, "backup").await,
+            ["refs/heads/feature", "refs/heads/main", "refs/tags/v1"]
+        );
+
+        run_git(
+            &work,
+            &["push", "--quiet", "origin", ":feature", ":refs/tags/v1"],
+        );
+        assert_eq!(
+            stale_refs(&local, "origin", Some("backup")).await.unwrap(),
+            ["refs/heads/feature", "refs/tags/v1"]
+        );
+
+        fetch(source, local.clone(), false, true).await.unwrap();
+        push_backup(local.clone(), backup, true, false)
+            .await
+            .unwrap();
+        assert_eq!(backup_refs(&local, "backup").await, ["refs/heads/main"]);
+    }
+
+    #[tokio::test]
+    async fn push_without_prune_keeps_deleted_branches() {
+        let dir = TempDir::new("no-prune");
+        let (source, work) = source_repo(&dir);
+        let (local, backup) = (dir.path("local"), dir.path("backup.git"));
+        init_bare(&backup, Some("main")).await.unwrap();
+
+        fetch(source.clone(), local.clone(), false, false)
+            .await
+            .unwrap();
+        push_backup(local.clone(), backup.clone(), false, false)
+            .await
+            .unwrap();
+
+        run_git(&work, &["push", "--quiet", "origin", ":feature"]);
+        fetch(source, local.clone(), false, false).await.unwrap();
+        push_backup(local.clone(), backup, false, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            backup_refs(&local, "backup").await,
+            ["refs/heads/feature", "refs/heads/main", "refs/tags/v1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn lfs_attributes_in_any_branch() {
+        let dir = TempDir::new("uses-lfs");
+        let (source, work) = source_repo(&dir);
+        assert!(!uses_lfs(&source).await.unwrap());
+
+        run_git(&work, &["checkout", "--quiet", "feature"]);
+        std::fs::create_dir_all(format!("{work}/assets")).unwrap();
+        std::fs::write(
+            format!("{work}/assets/.gitattributes"),
+            "*.png filter=lfs diff=lfs merge=lfs -text\n",
+        )
+        .unwrap();
+        run_git(&work, &["add", "."]);
+        run_git(&work, &["commit", "--quiet", "-m", "lfs"]);
+        run_git(&work, &["push", "--quiet", "origin", "feature"]);
+        assert!(uses_lfs(&source).await.unwrap());
+    }
+
+    async fn ref_names(path: &str) -> Vec<String> {
+        let out = git(vec!["-C", path, "for-each-ref", "--format=%(refname)"])
+            .await
+            .unwrap();
+        out.lines().map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn mirror_prunes_and_carries_notes() {
+        let dir = TempDir::new("mirror");
+        let (source, work) = source_repo(&dir);
+        let (local, backup) = (dir.path("local.git"), dir.path("backup.git"));
+        init_bare(&backup, Some("main")).await.unwrap();
+        run_git(&work, &["notes", "add", "-m", "reviewed"]);
+        run_git(&work, &["update-ref", "refs/custom/build", "HEAD"]);
+        run_git(
+            &work,
+            &["push", "--quiet", "origin", "refs/notes/*", "refs/custom/*"],
+        );
+
+        assert!(fetch_mirror(source.clone(), local.clone()).await.unwrap());
+        push_mirror(local.clone(), backup.clone(), false)
+            .await
+            .unwrap();
+        // the local mirror keeps all refs, backups get branches, tags and notes
+        assert_eq!(
+            ref_names(&local).await,
+            [
+                "refs/custom/build",
+                "refs/heads/feature",
+                "refs/heads/main",
+                "refs/notes/commits",
+                "refs/tags/v1"
+            ]
+        );
+        assert_eq!(
+            ref_names(&backup).await,
+            [
+                "refs/heads/feature",
+                "refs/heads/main",
+                "refs/notes/commits",
+                "refs/tags/v1"
+            ]
+        );
+
+        run_git(
+            &work,
+            &["push", "--quiet", "origin", ":feature", ":refs/tags/v1"],
+        );
+        assert!(!fetch_mirror(source, local.clone()).await.unwrap());
+        push_mirror(local.clone(), backup.clone(), false)
+            .await
+            .unwrap();
+        assert_eq!(
+            ref_names(&local).await,
+            ["refs/custom/build", "refs/heads/main", "refs/notes/commits"]
+        );
+        assert_eq!(
+            ref_names(&backup).await,
+            ["refs/heads/main", "refs/notes/commits"]
+        );
+    }
 }
@@ -0,0 +1,212 @@
+use anyhow::Result;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::Method;
+use serde::Serialize;
+use tracing::error;
+use url::Url;
+
+use crate::gitea::types;
+use crate::gitlab;
+use crate::rate_limit::RateLimiter;
+use crate::rest;
+
+const API_VERSION: &str = "v1";
+
+/// A client of Gitea and its fork Forgejo, they share the API
+pub struct Client {
+    api: rest::Client,
+}
+
+impl Client {
+    pub fn new(
+        token: &str,
+        url: &Url,
+        opp: Option<u32>,
+        disable_sync_date: bool,
+        timeout: Option<u32>,
+        api_rate: Option<f64>,
+        api_concurrency: Option<usize>,
+    ) -> Result<Self> {
+        let mut auth = HeaderValue::from_str(&format!("token {token}"))?;
+        auth.set_sensitive(true);
+        let headers = HeaderMap::from_iter([(header::AUTHORIZATION, auth)]);
+
+        Ok(Client {
+            api: rest::Client::new(
+                url.join(&format!("api/{API_VERSION}/"))?,
+                headers,
+                timeout,
+                ("limit", opp.unwrap_or(50)),
+                disable_sync_date,
+                RateLimiter::new(api_rate, api_concurrency),
+            )?,
+        })
+    }
+
+    pub async fn get_current_user(&self) -> reqwest::Result<types::User> {
+        self.api.get("user").await
+    }
+
+    /// Repositories of the organization or the user, repositories available to the token owner without it
+    pub async fn get_projects(
+        &self,
+        owner: Option<String>,
+        only_owned: bool,
+        exclude_archived: bool,
+    ) -> Result<Vec<gitlab::types::Project>> {
+        let repos: Vec<types::Repository> = match owner {
+            Some(owner) => {
+                if self.org_exist(&owner).await?.is_some() {
+                    self.api
+                        .get_all_pages(&format!("orgs/{owner}/repos"), "")
+                        .await?
+                } else {
+                    self.api
+                        .get_all_pages(&format!("users/{owner}/repos"), "")
+                        .await?
+                }
+            }
+            None => self.api.get_all_pages("user/repos", "").await?,
+        };
+        let login = if only_owned {
+            Some(self.get_current_user().await?.login)
+        } else {
+            None
+        };
+
+        let mut projects = Vec::with_capacity(repos.len());
+        for repo in repos {
+            if exclude_archived && repo.archived {
+                continue;
+            }
+            if login.as_ref().is_some_and(|l| *l != repo.owner.login) {
+                continue;
+            }
+            match repo.into_project() {
+                Ok(project) => projects.push(project),
+                Err(e) => error!("{e:#}, the repository is skipped"),
+            }
+        }
+        projects.retain(|p| !p.empty_repo);
+
+        Ok(projects)
+    }
+
+    pub async fn org_exist(&self, name: &str) -> reqwest::Result<Option<types::Organization>> {
+        self.api.get_optional(&format!("orgs/{name}")).await
+    }
+
+    pub async fn make_org(
+        &self,
+        name: &str,
+        visibility: types::Visibility,
+    ) -> reqwest::Result<types::Organization> {
+        #[derive(Serialize)]
+        struct MakeOrgRequest<'a> {
+            username: &'a str,
+            visibility: types::Visibility,
+        }
+
+        self.api
+            .send(
+                Method::POST,
+                "orgs",
+                Some(MakeOrgRequest {
+                    username: name,
+                    visibility,
+                }),
+            )
+            .await
+    }
+
+    pub async fn repo_exist(
+        &self,
+        owner: &str,
+        name: &str,
+    ) -> reqwest::Result<Option<types::Repository>> {
+        self.api.repo_exist(owner, name).await
+    }
+
+    /// Create a repository in the organization or in the account of the token owner
+    pub async fn make_repo(
+        &self,
+        org: Option<&str>,
+        name: &str,
+        info: &gitlab::types::Project,
+        private: bool,
+    ) -> reqwest::Result<types::Repository> {
+        self.api.make_repo(org, name, info, private).await
+    }
+
+    pub async fn update_repo(
+        &self,
+        repo: &types::Repository,
+        info: &gitlab::types::Project,
+        private: bool,
+    ) -> reqwest::Result<types::Repository> {
+        self.api.update_repo(repo, info, private).await
+    }
+
+    /// The branch must already exist in the repository
+    pub async fn update_default_branch(
+        &self,
+        repo: &types::Repository,
+        branch: &str,
+    ) -> reqwest::Result<types::Repository> {
+        self.api.update_default_branch(repo, branch).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    fn repo(owner: &str, name: &str) -> serde_json::Value {
+        json!({
+            "id": 1,
+            "name": name,
+            "full_name": format!("{owner}/{name}"),
+            "owner": {"login": owner},
+            "description": null,
+            "private": false,
+            "updated_at": null,
+            "default_branch": "main",
+            "clone_url": format!("https://gitea.example.com/{owner}/{name}.git"),
+            "ssh_url": format!("git@gitea.example.com:{owner}/{name}.git"),
+        })
+    }
+
+    #[tokio::test]
+    async fn get_projects_of_all_pages() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::parse(&format!("{}/", server.url())).unwrap();
+        let client = Client::new("token", &url, Some(1), true, None, None, None).unwrap();
+        server
+            .mock("GET", "/api/v1/orgs/org")
+            .match_header("authorization", "token token")
+            .with_body(r#"{"username": "org"}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v1/orgs/org/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+            .with_body(json!([repo("org", "alpha")]).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v1/orgs/org/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let projects = client
+            .get_projects(Some("org".to_string()), false, false)
+            .await
+            .unwrap();
+        assert_eq!(projects.len(), 1);
+        assert_eq!(projects[0].path_with_namespace, "org/alpha");
+    }
+}
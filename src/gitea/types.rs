@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{gitlab, rest};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct User {
+    pub login: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Organization {
+    /// The name of the organization used in paths.
+    pub username: String,
+}
+
+/// Visibility of organizations, `limited` ones are visible to all signed in users
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Private,
+    Limited,
+    Public,
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Repository {
+    pub id: u64,
+    pub name: String,
+    /// The name of the repository with its owner.
+    pub full_name: String,
+    pub owner: User,
+    pub description: Option<String>,
+    pub private: bool,
+    /// Whether the repository is visible only to signed in users.
+    #[serde(default)]
+    pub internal: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub empty: bool,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub default_branch: Option<String>,
+    /// The URL to clone the repository over HTTPS.
+    pub clone_url: String,
+    /// The URL to clone the repository over SSH.
+    pub ssh_url: String,
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+impl Repository {
+    /// The repository as a GitLab project, so it is synced like projects of GitLab instances
+    pub fn into_project(self) -> Result<gitlab::types::Project> {
+        let visibility = if self.private {
+            gitlab::types::Visibility::Private
+        } else if self.internal {
+            gitlab::types::Visibility::Internal
+        } else {
+            gitlab::types::Visibility::Public
+        };
+        Ok(gitlab::types::Project {
+            id: u32::try_from(self.id)
+                .with_context(|| format!("id of repository {} is too large", self.full_name))?,
+            description: self.description,
+            empty_repo: self.empty,
+            ssh_url_to_repo: self.ssh_url,
+            http_url_to_repo: self.clone_url,
+            name_with_namespace: format!("{} / {}", self.owner.login, self.name),
+            path: self.name.clone(),
+            name: self.name,
+            path_with_namespace: self.full_name,
+            last_activity_at: self.updated_at,
+            // there is no LFS switch per repository, LFS use is found after fetching
+            lfs_enabled: false,
+            wiki_enabled: false,
+            visibility: Some(visibility),
+            default_branch: self.default_branch,
+            topics: self.topics,
+            avatar_url: None,
+        })
+    }
+}
+
+impl rest::Repository for Repository {
+    fn full_name(&self) -> &str {
+        &self.full_name
+    }
+
+    fn clone_url(&self) -> &str {
+        &self.clone_url
+    }
+
+    fn ssh_url(&self) -> &str {
+        &self.ssh_url
+    }
+
+    fn default_branch(&self) -> Option<&str> {
+        self.default_branch.as_deref()
+    }
+}
@@ -0,0 +1,504 @@
+use anyhow::Result;
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::{Method, StatusCode};
+use serde::Serialize;
+use tracing::error;
+use url::Url;
+
+use crate::github::types;
+use crate::gitlab;
+use crate::rate_limit::RateLimiter;
+use crate::rest;
+
+const API_VERSION: &str = "2022-11-28";
+/// GitHub rejects requests without a user agent
+const USER_AGENT: &str = concat!("gitlobster/", env!("CARGO_PKG_VERSION"));
+const MAX_TOPIC_LENGTH: usize = 50;
+
+pub struct Client {
+    /// The REST API at `https://api.github.com/` or `https://<host>/api/v3/` for GitHub Enterprise
+    api: rest::Client,
+}
+
+impl Client {
+    pub fn new(
+        token: &str,
+        url: &Url,
+        opp: Option<u32>,
+        disable_sync_date: bool,
+        timeout: Option<u32>,
+        api_rate: Option<f64>,
+        api_concurrency: Option<usize>,
+    ) -> Result<Self> {
+        let mut auth = HeaderValue::from_str(&format!("Bearer {token}"))?;
+        auth.set_sensitive(true);
+        let headers = HeaderMap::from_iter([
+            (header::USER_AGENT, HeaderValue::from_static(USER_AGENT)),
+            (
+                header::ACCEPT,
+                HeaderValue::from_static("application/vnd.github+json"),
+            ),
+            (
+                header::HeaderName::from_static("x-github-api-version"),
+                HeaderValue::from_static(API_VERSION),
+            ),
+            (header::AUTHORIZATION, auth),
+        ]);
+
+        Ok(Client {
+            api: rest::Client::new(
+                url.clone(),
+                headers,
+                timeout,
+                ("per_page", opp.unwrap_or(100)),
+                disable_sync_date,
+                RateLimiter::new(api_rate, api_concurrency),
+            )?,
+        })
+    }
+
+    pub async fn get_current_user(&self) -> reqwest::Result<types::User> {
+        self.api.get("user").await
+    }
+
+    /// Repositories of the organization or the user, repositories available to the token owner without it
+    pub async fn get_projects(
+        &self,
+        owner: Option<String>,
+        only_owned: bool,
+        only_membership: bool,
+        exclude_archived: bool,
+    ) -> Result<Vec<gitlab::types::Project>> {
+        let (owned_filter, membership_filter) = if owner.is_some() {
+            (only_owned, only_membership)
+        } else {
+            (false, false)
+        };
+        let repos: Vec<types::Repository> = if let Some(owner) = owner {
+            match self
+                .api
+                .get_all_pages(&format!("orgs/{owner}/repos"), "type=all")
+                .await
+            {
+                Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {
+                    self.api
+                        .get_all_pages(&format!("users/{owner}/repos"), "type=owner")
+                        .await?
+                }
+                repos => repos?,
+            }
+        } else {
+            let affiliation = if only_owned {
+                "owner"
+            } else if only_membership {
+                "owner,collaborator"
+            } else {
+                "owner,collaborator,organization_member"
+            };
+            self.api
+                .get_all_pages("user/repos", &format!("affiliation={affiliation}"))
+                .await?
+        };
+
+        let mut projects = Vec::with_capacity(repos.len());
+        for repo in repos {
+            if exclude_archived && repo.archived {
+                continue;
+            }
+            // repositories of an owner are listed without affiliations,
+            // so they are filtered by permissions of the token owner
+            if owned_filter && !repo.permissions.admin
+                || membership_filter && !repo.permissions.push
+            {
+                continue;
+            }
+            // repositories with a size are not empty, others are checked by their commits
+            let empty = repo.size == 0 && self.is_empty(&repo).await?;
+            match repo.into_project(empty) {
+                Ok(project) => projects.push(project),
+                Err(e) => error!("{e:#}, the repository is skipped"),
+            }
+        }
+        projects.retain(|p| !p.empty_repo);
+
+        Ok(projects)
+    }
+
+    /// Whether the repository has no refs, GitHub answers with a conflict
+    /// to requests of commits of empty repositories
+    async fn is_empty(&self, repo: &types::Repository) -> reqwest::Result<bool> {
+        let path = format!("repos/{}/commits?per_page=1", repo.full_name);
+        match self.api.request(Method::GET, &path, None::<()>).await {
+            Ok(_) => Ok(false),
+            Err(e) if e.status() == Some(StatusCode::CONFLICT) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn get_organization(&self, org: &str) -> reqwest::Result<types::Organization> {
+        self.api.get(&format!("orgs/{org}")).await
+    }
+
+    pub async fn repo_exist(
+        &self,
+        owner: &str,
+        name: &str,
+    ) -> reqwest::Result<Option<types::Repository>> {
+        self.api.repo_exist(owner, name).await
+    }
+
+    /// Create a repository in the organization or in the account of the token owner
+    pub async fn make_repo(
+        &self,
+        org: Option<&str>,
+        name: &str,
+        info: &gitlab::types::Project,
+        private: bool,
+    ) -> reqwest::Result<types::Repository> {
+        self.api.make_repo(org, name, info, private).await
+    }
+
+    pub async fn update_repo(
+        &self,
+        repo: &types::Repository,
+        info: &gitlab::types::Project,
+        private: bool,
+    ) -> reqwest::Result<types::Repository> {
+        self.api.update_repo(repo, info, private).await
+    }
+
+    /// The branch must already exist in the repository
+    pub async fn update_default_branch(
+        &self,
+        repo: &types::Repository,
+        branch: &str,
+    ) -> reqwest::Result<types::Repository> {
+        self.api.update_default_branch(repo, branch).await
+    }
+
+    /// Replace topics of the repository, GitLab topics are converted to GitHub ones
+    pub async fn replace_topics(
+        &self,
+        repo: &types::Repository,
+        topics: &[String],
+    ) -> reqwest::Result<()> {
+        #[derive(Serialize)]
+        struct ReplaceTopicsRequest {
+            names: Vec<String>,
+        }
+
+        let mut names: Vec<String> = topics.iter().filter_map(|t| topic_name(t)).collect();
+        names.sort();
+        names.dedup();
+        let mut current = repo.topics.clone();
+        current.sort();
+        if names == current {
+            return Ok(());
+        }
+        self.api
+            .request(
+                Method::PUT,
+                &format!("repos/{}/topics", repo.full_name),
+                Some(ReplaceTopicsRequest { names }),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// GitHub topics contain only lowercase letters, digits and hyphens
+fn topic_name(topic: &str) -> Option<String> {
+    let name: String = topic
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    if name.is_empty() {
+        return None;
+    }
+    Some(name.chars().take(MAX_TOPIC_LENGTH).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    fn client(server: &mockito::Server, opp: Option<u32>) -> Client {
+        let url = Url::parse(&format!("{}/", server.url())).unwrap();
+        Client::new("token", &url, opp, true, None, None, None).unwrap()
+    }
+
+    fn repo(owner: &str, name: &str) -> serde_json::Value {
+        json!({
+            "id": 1,
+            "name": name,
+            "full_name": format!("{owner}/{name}"),
+            "owner": {"login": owner},
+            "description": null,
+            "private": true,
+            "size": 10,
+            "pushed_at": null,
+            "default_branch": "main",
+            "clone_url": format!("https://github.com/{owner}/{name}.git"),
+            "ssh_url": format!("git@github.com:{owner}/{name}.git"),
+            "topics": ["gitlab"],
+        })
+    }
+
+    #[tokio::test]
+    async fn missing_repo() {
+        let mut server = mockito::Server::new_async().await;
+        let client = client(&server, None);
+        server
+            .mock("GET", "/repos/org/missing")
+            .with_status(404)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/repos/org/alpha")
+            .match_header("authorization", "Bearer token")
+            .with_body(repo("org", "alpha").to_string())
+            .create_async()
+            .await;
+
+        assert!(client.repo_exist("org", "missing").await.unwrap().is_none());
+        let repo = client.repo_exist("org", "alpha").await.unwrap().unwrap();
+        assert_eq!(repo.full_name, "org/alpha");
+    }
+
+    #[tokio::test]
+    async fn make_repo_in_org_or_user_account() {
+        let mut server = mockito::Server::new_async().await;
+        let client = client(&server, None);
+        let in_org = server
+            .mock("POST", "/orgs/org/repos")
+            .match_body(Matcher::PartialJson(
+                json!({"name": "team-alpha", "private": true}),
+            ))
+            .with_status(201)
+            .with_body(repo("org", "team-alpha").to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let in_user = server
+            .mock("POST", "/user/repos")
+            .match_body(Matcher::PartialJson(
+                json!({"name": "team-alpha", "private": false}),
+            ))
+            .with_status(201)
+            .with_body(repo("user", "team-alpha").to_string())
+            .expect(1)
+            .create_async()
+            .await;
+
+        let info = gitlab::types::Project::stub(1, "team/alpha");
+        client
+            .make_repo(Some("org"), "team-alpha", &info, true)
+            .await
+            .unwrap();
+        client
+            .make_repo(None, "team-alpha", &info, false)
+            .await
+            .unwrap();
+
+        in_org.assert_async().await;
+        in_user.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn get_projects_of_all_pages() {
+        let mut server = mockito::Server::new_async().await;
+        let client = client(&server, Some(2));
+        server
+            .mock("GET", "/orgs/org/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+            .with_body(json!([repo("org", "alpha"), repo("org", "beta")]).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/orgs/org/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+            .with_body(json!([repo("org", "gamma")]).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/orgs/org/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "3".into()))
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let projects = client
+            .get_projects(Some("org".to_string()), false, false, false)
+            .await
+            .unwrap();
+        let paths: Vec<&str> = projects
+            .iter()
+            .map(|p| p.path_with_namespace.as_str())
+            .collect();
+        assert_eq!(paths, ["org/alpha", "org/beta", "org/gamma"]);
+    }
+
+    #[tokio::test]
+    async fn get_projects_of_user() {
+        let mut server = mockito::Server::new_async().await;
+        let client = client(&server, None);
+        server
+            .mock("GET", "/orgs/user/repos")
+            .match_query(Matcher::Any)
+            .with_status(404)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/users/user/repos")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("type".into(), "owner".into()),
+                Matcher::UrlEncoded("page".into(), "1".into()),
+            ]))
+            .with_body(json!([repo("user", "alpha")]).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/users/user/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let projects = client
+            .get_projects(Some("user".to_string()), false, false, false)
+            .await
+            .unwrap();
+        assert_eq!(projects[0].path_with_namespace, "user/alpha");
+    }
+
+    #[tokio::test]
+    async fn filter_repos_of_owner_by_permissions() {
+        let mut server = mockito::Server::new_async().await;
+        let client = client(&server, None);
+        let mut admin = repo("org", "admin");
+        admin["permissions"] = json!({"admin": true, "push": true});
+        let mut member = repo("org", "member");
+        member["permissions"] = json!({"admin": false, "push": true});
+        let mut other = repo("org", "other");
+        other["permissions"] = json!({"admin": false, "push": false});
+        // an id of another instance not fitting into GitLab ids is skipped
+        let mut large = repo("org", "large");
+        large["id"] = json!(u64::from(u32::MAX) + 1);
+        server
+            .mock("GET", "/orgs/org/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+            .with_body(json!([admin, member, other, large]).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/orgs/org/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+            .with_body("[]")
+            .create_async()
+            .await;
+
+        let paths = |projects: Vec<gitlab::types::Project>| -> Vec<String> {
+            projects
+                .into_iter()
+                .map(|p| p.path_with_namespace)
+                .collect()
+        };
+        let org = || Some("org".to_string());
+        let all = client
+            .get_projects(org(), false, false, false)
+            .await
+            .unwrap();
+        assert_eq!(paths(all), ["org/admin", "org/member", "org/other"]);
+        let owned = client
+            .get_projects(org(), true, false, false)
+            .await
+            .unwrap();
+        assert_eq!(paths(owned), ["org/admin"]);
+        let membership = client
+            .get_projects(org(), false, true, false)
+            .await
+            .unwrap();
+        assert_eq!(paths(membership), ["org/admin", "org/member"]);
+    }
+
+    #[tokio::test]
+    async fn skip_repos_without_refs() {
+        let mut server = mockito::Server::new_async().await;
+        let client = client(&server, None);
+        // the size of a recently pushed repository is not updated yet
+        let mut pushed = repo("org", "pushed");
+        pushed["size"] = json!(0);
+        let mut empty = repo("org", "empty");
+        empty["size"] = json!(0);
+        server
+            .mock("GET", "/orgs/org/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "1".into()))
+            .with_body(json!([repo("org", "alpha"), pushed, empty]).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/orgs/org/repos")
+            .match_query(Matcher::UrlEncoded("page".into(), "2".into()))
+            .with_body("[]")
+            .create_async()
+            .await;
+        let commits = server
+            .mock("GET", "/repos/org/pushed/commits")
+            .match_query(Matcher::Any)
+            .with_body(r#"[{"sha": "abc"}]"#)
+            .expect(1)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/repos/org/empty/commits")
+            .match_query(Matcher::Any)
+            .with_status(409)
+            .with_body(r#"{"message": "Git Repository is empty."}"#)
+            .create_async()
+            .await;
+
+        let projects = client
+            .get_projects(Some("org".to_string()), false, false, false)
+            .await
+            .unwrap();
+        let paths: Vec<&str> = projects
+            .iter()
+            .map(|p| p.path_with_namespace.as_str())
+            .collect();
+        assert_eq!(paths, ["org/alpha", "org/pushed"]);
+        commits.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn replace_changed_topics() {
+        let mut server = mockito::Server::new_async().await;
+        let client = client(&server, None);
+        let replaced = server
+            .mock("PUT", "/repos/org/alpha/topics")
+            .match_body(Matcher::Json(json!({"names": ["gitlab", "rust-lang"]})))
+            .with_body(r#"{"names": ["gitlab", "rust-lang"]}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let repo: types::Repository = serde_json::from_value(repo("org", "alpha")).unwrap();
+        let unchanged = ["GitLab".to_string()];
+        client.replace_topics(&repo, &unchanged).await.unwrap();
+        let changed = ["GitLab".to_string(), "Rust Lang".to_string()];
+        client.replace_topics(&repo, &changed).await.unwrap();
+
+        replaced.assert_async().await;
+    }
+
+    #[test]
+    fn topic_names() {
+        assert_eq!(topic_name("Rust Lang").unwrap(), "rust-lang");
+        assert_eq!(topic_name("--c++--").unwrap(), "c");
+        assert_eq!(topic_name("???"), None);
+        assert_eq!(topic_name(&"a".repeat(60)).unwrap().len(), MAX_TOPIC_LENGTH);
+    }
+}
@@ -0,0 +1,4 @@
+pub use client::Client;
+
+pub mod client;
+pub mod types;
@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{gitlab, rest};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct User {
+    pub login: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Organization {
+    pub login: String,
+}
+
+/// Permissions of the token owner, they are reported only to authenticated requests
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Permissions {
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(default)]
+    pub push: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Repository {
+    pub id: u64,
+    pub name: String,
+    /// The name of the repository with its owner.
+    pub full_name: String,
+    pub owner: User,
+    pub description: Option<String>,
+    pub private: bool,
+    /// One of `public`, `private` or `internal`.
+    pub visibility: Option<gitlab::types::Visibility>,
+    #[serde(default)]
+    pub archived: bool,
+    /// The size of the repository in kilobytes, it is updated lazily,
+    /// so recently pushed repositories may have no size.
+    #[serde(default)]
+    pub size: u64,
+    pub pushed_at: Option<DateTime<Utc>>,
+    pub default_branch: Option<String>,
+    /// The URL to clone the repository over HTTPS.
+    pub clone_url: String,
+    /// The URL to clone the repository over SSH.
+    pub ssh_url: String,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub permissions: Permissions,
+}
+
+impl Repository {
+    /// The repository as a GitLab project, so it is synced like projects of GitLab instances,
+    /// GitHub does not report whether the repository has commits
+    pub fn into_project(self, empty_repo: bool) -> Result<gitlab::types::Project> {
+        let visibility = self.visibility.unwrap_or(if self.private {
+            gitlab::types::Visibility::Private
+        } else {
+            gitlab::types::Visibility::Public
+        });
+        Ok(gitlab::types::Project {
+            id: u32::try_from(self.id)
+                .with_context(|| format!("id of repository {} is too large", self.full_name))?,
+            description: self.description,
+            empty_repo,
+            ssh_url_to_repo: self.ssh_url,
+            http_url_to_repo: self.clone_url,
+            name_with_namespace: format!("{} / {}", self.owner.login, self.name),
+            path: self.name.clone(),
+            name: self.name,
+            path_with_namespace: self.full_name,
+            last_activity_at: self.pushed_at,
+            // there is no LFS switch per repository, LFS use is found after fetching
+            lfs_enabled: false,
+            wiki_enabled: false,
+            visibility: Some(visibility),
+            default_branch: self.default_branch,
+            topics: self.topics,
+            avatar_url: None,
+        })
+    }
+}
+
+impl rest::Repository for Repository {
+    fn full_name(&self) -> &str {
+        &self.full_name
+    }
+
+    fn clone_url(&self) -> &str {
+        &self.clone_url
+    }
+
+    fn ssh_url(&self) -> &str {
+        &self.ssh_url
+    }
+
+    fn default_branch(&self) -> Option<&str> {
+        self.default_branch.as_deref()
+    }
+}
@@ -1,21 +1,89 @@
-use anyhow::Result;
-use chrono::Utc;
-use reqwest::{header::HeaderValue, Method, RequestBuilder, Response};
-use serde::Serialize;
+use anyhow::{bail, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::{stream, TryStreamExt};
+use reqwest::{
+    header::{HeaderValue, ETAG, LAST_MODIFIED, LOCATION},
+    multipart, redirect, Body, Method, RequestBuilder, Response,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
 use tracing::info;
 use url::Url;
 
 use crate::gitlab::types;
+use crate::rate_limit::RateLimiter;
+use crate::retry;
 
 const API_VERSION: &str = "v4";
+const MAX_REDIRECTS: usize = 10;
+
+/// A body streaming the file, the file is opened on the first read,
+/// so a body made for every attempt of a request sends the whole file again
+fn file_body(path: &Path) -> Body {
+    let file = tokio::fs::File::open(path.to_path_buf());
+    Body::wrap_stream(stream::once(file).map_ok(ReaderStream::new).try_flatten())
+}
+
+/// A marker of the source snippet at the end of descriptions of synced snippets
+fn snippet_marker(source: &types::Snippet) -> String {
+    format!("🦞 Source: {}", source.web_url)
+}
+
+/// Find the snippet synced from `source` among `snippets` by the source URL in its description
+pub fn synced_snippet<'a>(
+    snippets: &'a [types::Snippet],
+    source: &types::Snippet,
+) -> Option<&'a types::Snippet> {
+    let marker = snippet_marker(source);
+    snippets.iter().find(|s| {
+        s.description
+            .as_deref()
+            .is_some_and(|d| d.ends_with(&marker))
+    })
+}
+
+/// A version of a file by the `ETag` and `Last-Modified` headers of its server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileVersion {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl FileVersion {
+    fn from_response(resp: &Response) -> Option<Self> {
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_string)
+        };
+        let version = Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+        (version.etag.is_some() || version.last_modified.is_some()).then_some(version)
+    }
+}
+
+pub struct DownloadedFile {
+    pub size: u64,
+    pub version: Option<FileVersion>,
+}
 
 pub struct Client {
     url: Url,
     http: reqwest::Client,
+    /// A client without automatic redirects for downloads of files
+    downloads: reqwest::Client,
     disable_sync_date: bool,
     token: String,
     limit: u32,
+    rate_limiter: RateLimiter,
 }
 
 impl Client {
@@ -25,12 +93,17 @@ impl Client {
         opp: Option<u32>,
         disable_sync_date: bool,
         timeout: Option<u32>,
+        api_rate: Option<f64>,
+        api_concurrency: Option<usize>,
     ) -> Result<Self> {
         let mut http = reqwest::ClientBuilder::new();
+        let mut downloads = reqwest::ClientBuilder::new().redirect(redirect::Policy::none());
         if let Some(timeout) = timeout {
             http = http.timeout(Duration::from_secs(timeout.into()));
+            downloads = downloads.timeout(Duration::from_secs(timeout.into()));
         }
         let http = http.build()?;
+        let downloads = downloads.build()?;
         let limit = opp.unwrap_or(100);
         let token = token.to_string();
 
@@ -39,9 +112,11 @@ impl Client {
         Ok(Client {
             url,
             http,
+            downloads,
             disable_sync_date,
             token,
             limit,
+            rate_limiter: RateLimiter::new(api_rate, api_concurrency),
         })
     }
 
@@ -80,10 +155,30 @@ impl Client {
         query: Option<String>,
         json: Option<J>,
     ) -> reqwest::Result<Response> {
-        self.build_request(m, path, query, json)
-            .send()
-            .await?
-            .error_for_status()
+        let path = path.into();
+        self.send(&format!("{m} {path}"), || {
+            self.build_request(m.clone(), path.clone(), query.clone(), json.as_ref())
+        })
+        .await
+    }
+
+    /// Send a request built by `build` for every attempt, with retries and rate limiting
+    async fn send(
+        &self,
+        name: &str,
+        build: impl Fn() -> RequestBuilder,
+    ) -> reqwest::Result<Response> {
+        retry::run(
+            name,
+            || async {
+                let _permit = self.rate_limiter.wait().await;
+                let resp = build().send().await?;
+                self.rate_limiter.update(&resp);
+                resp.error_for_status()
+            },
+            retry::Policy::is_retryable_http,
+        )
+        .await
     }
 
     pub async fn get_project(&self, path: String) -> reqwest::Result<types::Project> {
@@ -112,6 +207,351 @@ impl Client {
         Client::check_exists(self.get_project(path).await)
     }
 
+    /// Whether the project wiki has pages, GitLab creates the wiki repository with the first page
+    pub async fn has_wiki_pages(&self, project_id: u32) -> reqwest::Result<bool> {
+        let pages = self
+            .request(
+                Method::GET,
+                format!("projects/{project_id}/wikis"),
+                None,
+                None::<()>,
+            )
+            .await?
+            .json::<Vec<types::WikiPage>>()
+            .await?;
+        Ok(!pages.is_empty())
+    }
+
+    /// Whether the group wiki has pages, group wikis are available only in GitLab Premium,
+    /// so a missing wiki is not an error
+    pub async fn has_group_wiki_pages(&self, group_id: u32) -> reqwest::Result<bool> {
+        let resp = self
+            .request(
+                Method::GET,
+                format!("groups/{group_id}/wikis"),
+                None,
+                None::<()>,
+            )
+            .await;
+        let resp = match resp {
+            Err(e) if e.status() == Some(reqwest::StatusCode::FORBIDDEN) => return Ok(false),
+            resp => Client::check_exists(resp)?,
+        };
+        let Some(resp) = resp else {
+            return Ok(false);
+        };
+        Ok(!resp.json::<Vec<types::WikiPage>>().await?.is_empty())
+    }
+
+    /// Get all pages of a list, pages are followed by the `x-next-page` header
+    async fn get_all_pages<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: Option<&str>,
+    ) -> reqwest::Result<Vec<T>> {
+        let mut items: Vec<T> = vec![];
+        let mut page = "1".to_string();
+        loop {
+            let mut page_query = format!("per_page={}&page={page}", self.limit);
+            if let Some(query) = query {
+                page_query = format!("{page_query}&{query}");
+            }
+            let resp = self
+                .request(Method::GET, path, Some(page_query), None::<()>)
+                .await?;
+            let next_page = resp
+                .headers()
+                .get("x-next-page")
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            items.append(&mut resp.json::<Vec<T>>().await?);
+            if next_page.is_empty() {
+                return Ok(items);
+            }
+            page = next_page;
+        }
+    }
+
+    pub async fn get_labels(&self, project_id: u32) -> reqwest::Result<Vec<serde_json::Value>> {
+        self.get_all_pages(&format!("projects/{project_id}/labels"), None)
+            .await
+    }
+
+    /// Issues or merge requests (`kind`) of the project updated after the time, all of them without it
+    pub async fn get_updated_items(
+        &self,
+        project_id: u32,
+        kind: &str,
+        updated_after: Option<DateTime<Utc>>,
+    ) -> reqwest::Result<Vec<serde_json::Value>> {
+        let mut query = "scope=all&state=all&order_by=updated_at&sort=asc".to_string();
+        if let Some(updated_after) = updated_after {
+            let updated_after = updated_after.to_rfc3339_opts(SecondsFormat::Secs, true);
+            query += &format!("&updated_after={}", urlencoding::encode(&updated_after));
+        }
+        self.get_all_pages(&format!("projects/{project_id}/{kind}"), Some(&query))
+            .await
+    }
+
+    /// Discussions with notes of an issue or a merge request (`kind`)
+    pub async fn get_discussions(
+        &self,
+        project_id: u32,
+        kind: &str,
+        iid: u64,
+    ) -> reqwest::Result<Vec<serde_json::Value>> {
+        self.get_all_pages(
+            &format!("projects/{project_id}/{kind}/{iid}/discussions"),
+            None,
+        )
+        .await
+    }
+
+    /// Personal snippets of the current user
+    pub async fn get_snippets(&self) -> reqwest::Result<Vec<types::Snippet>> {
+        self.get_all_pages("snippets", None).await
+    }
+
+    pub async fn get_project_snippets(
+        &self,
+        project_id: u32,
+    ) -> reqwest::Result<Vec<types::Snippet>> {
+        self.get_all_pages(&format!("projects/{project_id}/snippets"), None)
+            .await
+    }
+
+    /// Update the snippet `existing` synced from `source` earlier, or create it with the files
+    /// (paths with contents). Personal snippets are used without `project_id`
+    pub async fn make_snippet(
+        &self,
+        project_id: Option<u32>,
+        source: &types::Snippet,
+        existing: Option<&types::Snippet>,
+        files: Vec<(String, String)>,
+    ) -> reqwest::Result<types::Snippet> {
+        #[derive(Serialize)]
+        struct SnippetFile {
+            file_path: String,
+            content: String,
+        }
+
+        #[derive(Serialize)]
+        struct MakeSnippetRequest {
+            title: String,
+            description: String,
+            visibility: String,
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            files: Vec<SnippetFile>,
+        }
+
+        let method = match project_id {
+            Some(id) => format!("projects/{id}/snippets"),
+            None => "snippets".to_string(),
+        };
+        let mut data = MakeSnippetRequest {
+            title: source.title.clone(),
+            description: format!(
+                "{} {}",
+                source.description.clone().unwrap_or_default(),
+                snippet_marker(source)
+            ),
+            visibility: source.visibility.clone(),
+            files: vec![],
+        };
+
+        let resp = if let Some(existing) = existing {
+            self.request(
+                Method::PUT,
+                format!("{method}/{}", existing.id),
+                None,
+                Some(&data),
+            )
+            .await?
+        } else {
+            data.files = files
+                .into_iter()
+                .map(|(file_path, content)| SnippetFile { file_path, content })
+                .collect();
+            self.request(Method::POST, &method, None, Some(&data))
+                .await?
+        };
+        resp.json::<types::Snippet>().await
+    }
+
+    /// Releases of the project as returned by GitLab, with all their metadata
+    pub async fn get_releases(&self, project_id: u32) -> reqwest::Result<Vec<serde_json::Value>> {
+        self.get_all_pages(&format!("projects/{project_id}/releases"), None)
+            .await
+    }
+
+    pub async fn get_generic_packages(
+        &self,
+        project_id: u32,
+    ) -> reqwest::Result<Vec<types::Package>> {
+        self.get_all_pages(
+            &format!("projects/{project_id}/packages"),
+            Some("package_type=generic"),
+        )
+        .await
+    }
+
+    pub async fn get_package_files(
+        &self,
+        project_id: u32,
+        package_id: u32,
+    ) -> reqwest::Result<Vec<types::PackageFile>> {
+        self.get_all_pages(
+            &format!("projects/{project_id}/packages/{package_id}/package_files"),
+            None,
+        )
+        .await
+    }
+
+    pub async fn schedule_export(&self, project_id: u32) -> reqwest::Result<()> {
+        self.request(
+            Method::POST,
+            format!("projects/{project_id}/export"),
+            None,
+            None::<()>,
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_export_status(
+        &self,
+        project_id: u32,
+    ) -> reqwest::Result<types::ProjectExport> {
+        self.request(
+            Method::GET,
+            format!("projects/{project_id}/export"),
+            None,
+            None::<()>,
+        )
+        .await?
+        .json::<types::ProjectExport>()
+        .await
+    }
+
+    /// Request a file following redirects manually, so the token is sent only to the GitLab
+    /// instance and not to object storages or external hosts
+    async fn request_file(&self, m: Method, mut url: Url) -> Result<Response> {
+        for _ in 0..MAX_REDIRECTS {
+            let own = url.origin() == self.url.origin();
+            let resp = self
+                .send(&format!("{m} {}", url.path()), || {
+                    let req = self.downloads.request(m.clone(), url.clone());
+                    if own {
+                        req.header("PRIVATE-TOKEN", &self.token)
+                    } else {
+                        req
+                    }
+                })
+                .await?;
+            if !resp.status().is_redirection() {
+                return Ok(resp);
+            }
+            let Some(location) = resp.headers().get(LOCATION).and_then(|l| l.to_str().ok()) else {
+                return Ok(resp);
+            };
+            url = url.join(location)?;
+        }
+        bail!("too many redirects while downloading {}", url.path())
+    }
+
+    /// The version of a file by an API path or an absolute URL, files without validators
+    /// and files of servers rejecting HEAD requests have no version
+    pub async fn file_version(&self, url: &str) -> Result<Option<FileVersion>> {
+        match self.request_file(Method::HEAD, self.url.join(url)?).await {
+            Ok(resp) => Ok(FileVersion::from_response(&resp)),
+            Err(e)
+                if e.downcast_ref::<reqwest::Error>()
+                    .is_some_and(|e| e.status().is_some()) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Download a file by an API path or an absolute URL to `dst`
+    pub async fn download(&self, url: &str, dst: &Path) -> Result<DownloadedFile> {
+        let mut resp = self.request_file(Method::GET, self.url.join(url)?).await?;
+        let version = FileVersion::from_response(&resp);
+
+        if let Some(dir) = dst.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // a previous file is replaced only by a completely downloaded one
+        let mut tmp_path = dst.as_os_str().to_owned();
+        tmp_path.push(".part");
+        let tmp_path = PathBuf::from(tmp_path);
+        let mut file = tokio::fs::File::create(&tmp_path).await?;
+        let mut size = 0;
+        while let Some(chunk) = resp.chunk().await? {
+            file.write_all(&chunk).await?;
+            size += chunk.len() as u64;
+        }
+        file.flush().await?;
+        tokio::fs::rename(tmp_path, dst).await?;
+        Ok(DownloadedFile { size, version })
+    }
+
+    /// Import an export archive as a new project, an existing project with the path is overwritten
+    pub async fn import_project(
+        &self,
+        archive: &Path,
+        namespace: Option<u32>,
+        path: &str,
+        name: &str,
+    ) -> Result<types::ProjectImport> {
+        let size = tokio::fs::metadata(archive).await?.len();
+        let file_name = archive
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let url = self.url.join("projects/import")?;
+        info!("{}", url);
+
+        let resp = self
+            .send("POST projects/import", || {
+                let mut form = multipart::Form::new()
+                    .text("path", path.to_string())
+                    .text("name", name.to_string())
+                    .text("overwrite", "true")
+                    .part(
+                        "file",
+                        multipart::Part::stream_with_length(file_body(archive), size)
+                            .file_name(file_name.clone()),
+                    );
+                if let Some(namespace) = namespace {
+                    form = form.text("namespace", namespace.to_string());
+                }
+                self.http
+                    .post(url.clone())
+                    .header("PRIVATE-TOKEN", &self.token)
+                    .multipart(form)
+            })
+            .await?;
+        Ok(resp.json::<types::ProjectImport>().await?)
+    }
+
+    pub async fn get_import_status(
+        &self,
+        project_id: u32,
+    ) -> reqwest::Result<types::ProjectImport> {
+        self.request(
+            Method::GET,
+            format!("projects/{project_id}/import"),
+            None,
+            None::<()>,
+        )
+        .await?
+        .json::<types::ProjectImport>()
+        .await
+    }
+
     /// Parse value of Link header
     /// ```
     /// let link = r#"<https://gitlab.example.com/api/v4/projects?per_page=50&order_by=id&sort=asc; rel="next""#;
@@ -230,6 +670,7 @@ impl Client {
         slug: String,
         group_id: u32,
         info: &types::Project,
+        visibility: Option<types::Visibility>,
     ) -> reqwest::Result<types::Project> {
         #[derive(Serialize)]
         struct MakeProjectRequest {
@@ -237,6 +678,9 @@ impl Client {
             description: String,
             path: String,
             namespace_id: u32,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            visibility: Option<types::Visibility>,
+            topics: Vec<String>,
         }
 
         let name = info.name.clone();
@@ -249,6 +693,8 @@ impl Client {
             description,
             path,
             namespace_id,
+            visibility,
+            topics: info.topics.clone(),
         };
         self.request(Method::POST, "projects", None, Some(data))
             .await?
@@ -260,14 +706,22 @@ impl Client {
         &self,
         project: &types::Project,
         info: &types::Project,
+        visibility: Option<types::Visibility>,
     ) -> reqwest::Result<types::Project> {
         #[derive(Serialize)]
         struct UpdateProjectRequest {
             description: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            visibility: Option<types::Visibility>,
+            topics: Vec<String>,
         }
 
         let description = self.make_project_description(info.description.clone());
-        let data = &UpdateProjectRequest { description };
+        let data = &UpdateProjectRequest {
+            description,
+            visibility,
+            topics: info.topics.clone(),
+        };
 
         self.request(
             Method::PUT,
@@ -280,6 +734,59 @@ impl Client {
         .await
     }
 
+    /// The branch must already exist in the repository of the project
+    pub async fn update_default_branch(
+        &self,
+        project_id: u32,
+        branch: &str,
+    ) -> reqwest::Result<types::Project> {
+        #[derive(Serialize)]
+        struct UpdateDefaultBranchRequest<'a> {
+            default_branch: &'a str,
+        }
+
+        self.request(
+            Method::PUT,
+            format!("projects/{project_id}"),
+            None,
+            Some(&UpdateDefaultBranchRequest {
+                default_branch: branch,
+            }),
+        )
+        .await?
+        .json::<types::Project>()
+        .await
+    }
+
+    /// Upload the avatar of the project, the avatar is removed if `avatar` is `None`
+    pub async fn update_avatar(&self, project_id: u32, avatar: Option<&Path>) -> Result<()> {
+        let file = match avatar {
+            Some(path) => Some((
+                tokio::fs::read(path).await?,
+                path.file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            )),
+            None => None,
+        };
+        let url = self.url.join(&format!("projects/{project_id}"))?;
+        self.send(&format!("PUT projects/{project_id}"), || {
+            let form = match &file {
+                Some((content, name)) => multipart::Form::new().part(
+                    "avatar",
+                    multipart::Part::bytes(content.clone()).file_name(name.clone()),
+                ),
+                None => multipart::Form::new().text("avatar", ""),
+            };
+            self.http
+                .put(url.clone())
+                .header("PRIVATE-TOKEN", &self.token)
+                .multipart(form)
+        })
+        .await?;
+        Ok(())
+    }
+
     pub async fn get_group(&self, path: &str) -> reqwest::Result<types::Group> {
         let path = urlencoding::encode(path);
         self.request(Method::GET, format!("groups/{path}"), None, None::<()>)
@@ -297,18 +804,22 @@ impl Client {
         name: String,
         path: String,
         parent_id: Option<u32>,
+        visibility: Option<types::Visibility>,
     ) -> reqwest::Result<types::Group> {
         #[derive(Serialize)]
         struct MakeGroupRequest {
             name: String,
             path: String,
             parent_id: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            visibility: Option<types::Visibility>,
         }
 
         let data = &MakeGroupRequest {
             name,
             path,
             parent_id,
+            visibility,
         };
 
         self.request(Method::POST, "groups", None, Some(data))
@@ -323,9 +834,54 @@ impl Client {
         groups: Vec<types::Group>,
         root_group: &Option<types::Group>,
         project_info: &types::Project,
+        visibility: Option<types::Visibility>,
     ) -> reqwest::Result<types::Project> {
-        let mut parent_id = root_group.as_ref().map(|gr| gr.id);
         let project_slug = path.pop().expect("invalid project path");
+        let (parent, current_namespace) =
+            self.make_namespace(groups, root_group, visibility).await?;
+
+        // a project cannot be more visible than its group
+        let visibility = match (
+            visibility.or(project_info.visibility),
+            parent.as_ref().and_then(|gr| gr.visibility),
+        ) {
+            (Some(v), Some(max)) => Some(v.min(max)),
+            (v, _) => v,
+        };
+
+        match self
+            .project_exist(format!("{current_namespace}/{project_slug}"))
+            .await?
+        {
+            Some(p) => self.update_project(&p, project_info, visibility).await,
+            None => {
+                self.make_project(
+                    project_slug,
+                    parent
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "Parent group for project {} not found",
+                                &project_info.name_with_namespace
+                            )
+                        })
+                        .id,
+                    project_info,
+                    visibility,
+                )
+                .await
+            }
+        }
+    }
+
+    /// Create missing groups of the namespace in the root group with the visibility of the source
+    /// groups or the override, returns the last group and its full path
+    pub async fn make_namespace(
+        &self,
+        groups: Vec<types::Group>,
+        root_group: &Option<types::Group>,
+        visibility: Option<types::Visibility>,
+    ) -> reqwest::Result<(Option<types::Group>, String)> {
+        let mut parent = root_group.clone();
         let mut current_namespace = root_group
             .as_ref()
             .map(|gr| gr.full_path.clone())
@@ -333,39 +889,34 @@ impl Client {
 
         for group in groups {
             current_namespace = if current_namespace.is_empty() {
-                group.name.clone()
+                group.path.clone()
             } else {
                 format!("{}/{}", current_namespace, &group.path)
             };
             let group = if let Some(group) = self.group_exist(&current_namespace).await? {
                 group
             } else {
-                self.make_subgroup(group.name.clone(), group.path.clone(), parent_id)
-                    .await?
+                // a group cannot be more visible than its parent
+                let visibility = match (
+                    visibility.or(group.visibility),
+                    parent.as_ref().and_then(|gr| gr.visibility),
+                ) {
+                    (Some(v), Some(max)) => Some(v.min(max)),
+                    (v, _) => v,
+                };
+                self.make_subgroup(
+                    group.name.clone(),
+                    group.path.clone(),
+                    parent.as_ref().map(|gr| gr.id),
+                    visibility,
+                )
+                .await?
             };
 
-            parent_id = Some(group.id);
+            parent = Some(group);
         }
 
-        match self
-            .project_exist(format!("{current_namespace}/{project_slug}"))
-            .await?
-        {
-            Some(p) => self.update_project(&p, project_info).await,
-            None => {
-                self.make_project(
-                    project_slug,
-                    parent_id.unwrap_or_else(|| {
-                        panic!(
-                            "Parent group for project {} not found",
-                            &project_info.name_with_namespace
-                        )
-                    }),
-                    project_info,
-                )
-                .await
-            }
-        }
+        Ok((parent, current_namespace))
     }
 
     pub async fn get_current_user(&self) -> reqwest::Result<types::User> {
@@ -375,3 +926,209 @@ impl Client {
             .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gitlab::types::Visibility;
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn import_streams_archive_on_every_attempt() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::parse(&format!("{}/", server.url())).unwrap();
+        let client = Client::new("token", &url, None, true, None, None, None).unwrap();
+
+        let archive =
+            std::env::temp_dir().join(format!("gitlobster-import-{}.tar.gz", std::process::id()));
+        std::fs::write(&archive, "archive content").unwrap();
+
+        let body = Matcher::AllOf(vec![
+            Matcher::Regex("archive content".to_string()),
+            Matcher::Regex(r#"name="path"\r\n\r\nalpha"#.to_string()),
+        ]);
+        let unavailable = server
+            .mock("POST", "/api/v4/projects/import")
+            .match_body(body.clone())
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let imported = server
+            .mock("POST", "/api/v4/projects/import")
+            .match_body(body)
+            .with_status(201)
+            .with_body(r#"{"id": 7, "import_status": "scheduled", "import_error": null}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let import = client
+            .import_project(&archive, None, "alpha", "Alpha")
+            .await
+            .unwrap();
+        std::fs::remove_file(&archive).unwrap();
+
+        assert_eq!(import.id, 7);
+        unavailable.assert_async().await;
+        imported.assert_async().await;
+    }
+
+    fn snippet(id: u32, description: Option<&str>) -> types::Snippet {
+        types::Snippet {
+            id,
+            title: format!("snippet {id}"),
+            description: description.map(str::to_string),
+            visibility: "private".to_string(),
+            web_url: format!("https://gitlab.example.com/-/snippets/{id}"),
+            ssh_url_to_repo: format!("git@gitlab.example.com:snippets/{id}.git"),
+            http_url_to_repo: format!("https://gitlab.example.com/snippets/{id}.git"),
+        }
+    }
+
+    #[test]
+    fn synced_snippet_by_marker() {
+        let source = snippet(1, Some("notes"));
+        let backups = [
+            snippet(7, None),
+            // the URL of another snippet starting with the same one
+            snippet(
+                8,
+                Some("🦞 Source: https://gitlab.example.com/-/snippets/10"),
+            ),
+            snippet(
+                9,
+                Some("notes 🦞 Source: https://gitlab.example.com/-/snippets/1"),
+            ),
+        ];
+        assert_eq!(synced_snippet(&backups, &source).map(|s| s.id), Some(9));
+        assert!(synced_snippet(&backups[..2], &source).is_none());
+    }
+
+    fn group(id: u32, full_path: &str, visibility: Visibility) -> types::Group {
+        types::Group {
+            id,
+            name: full_path.rsplit('/').next().unwrap().to_string(),
+            path: full_path.rsplit('/').next().unwrap().to_string(),
+            full_path: full_path.to_string(),
+            visibility: Some(visibility),
+            ..Default::default()
+        }
+    }
+
+    fn project(id: u32, path: &str, visibility: Visibility) -> types::Project {
+        types::Project {
+            visibility: Some(visibility),
+            ..types::Project::stub(id, path)
+        }
+    }
+
+    #[tokio::test]
+    async fn new_subgroups_and_projects_clamped_by_parents() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::parse(&format!("{}/", server.url())).unwrap();
+        let client = Client::new("token", &url, None, true, None, None, None).unwrap();
+        let root = group(1, "backup", Visibility::Internal);
+        let created = group(2, "backup/team", Visibility::Internal);
+
+        let groups = [
+            ("/api/v4/groups/backup%2Fteam", 404, "{}".to_string()),
+            (
+                "/api/v4/projects/backup%2Fteam%2Falpha",
+                404,
+                "{}".to_string(),
+            ),
+        ];
+        for (path, status, body) in groups {
+            server
+                .mock("GET", path)
+                .with_status(status)
+                .with_body(body)
+                .create_async()
+                .await;
+        }
+        // the public source group becomes internal in the internal root group
+        let make_group = server
+            .mock("POST", "/api/v4/groups")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"path": "team", "parent_id": 1, "visibility": "internal"}"#.to_string(),
+            ))
+            .with_status(201)
+            .with_body(serde_json::to_string(&created).unwrap())
+            .expect(1)
+            .create_async()
+            .await;
+        let make_project = server
+            .mock("POST", "/api/v4/projects")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"path": "alpha", "namespace_id": 2, "visibility": "internal"}"#.to_string(),
+            ))
+            .with_status(201)
+            .with_body(
+                serde_json::to_string(&project(3, "backup/team/alpha", Visibility::Internal))
+                    .unwrap(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let source = project(7, "team/alpha", Visibility::Public);
+        client
+            .make_project_with_namespace(
+                vec!["team".to_string(), "alpha".to_string()],
+                vec![group(5, "team", Visibility::Public)],
+                &Some(root),
+                &source,
+                None,
+            )
+            .await
+            .unwrap();
+        make_group.assert_async().await;
+        make_project.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn visibility_override() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::parse(&format!("{}/", server.url())).unwrap();
+        let client = Client::new("token", &url, None, true, None, None, None).unwrap();
+        let existing = group(2, "team", Visibility::Public);
+
+        server
+            .mock("GET", "/api/v4/groups/team")
+            .with_body(serde_json::to_string(&existing).unwrap())
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/v4/projects/team%2Falpha")
+            .with_body(
+                serde_json::to_string(&project(3, "team/alpha", Visibility::Public)).unwrap(),
+            )
+            .create_async()
+            .await;
+        let update = server
+            .mock("PUT", "/api/v4/projects/3")
+            .match_body(Matcher::PartialJsonString(
+                r#"{"visibility": "private"}"#.to_string(),
+            ))
+            .with_body(
+                serde_json::to_string(&project(3, "team/alpha", Visibility::Private)).unwrap(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        let source = project(7, "team/alpha", Visibility::Public);
+        client
+            .make_project_with_namespace(
+                vec!["team".to_string(), "alpha".to_string()],
+                vec![existing.clone()],
+                &None,
+                &source,
+                Some(Visibility::Private),
+            )
+            .await
+            .unwrap();
+        update.assert_async().await;
+    }
+}
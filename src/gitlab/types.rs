@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -25,6 +26,128 @@ pub struct Project {
     pub path: String,
     /// The path to the project's repository with its namespace.
     pub path_with_namespace: String,
+    /// The time of the last activity in the project.
+    pub last_activity_at: Option<DateTime<Utc>>,
+    /// Whether Git LFS is enabled for the project.
+    #[serde(default)]
+    pub lfs_enabled: bool,
+    /// Whether the wiki is enabled for the project.
+    #[serde(default)]
+    pub wiki_enabled: bool,
+    pub visibility: Option<Visibility>,
+    /// The default branch, missing in projects with an empty repository.
+    pub default_branch: Option<String>,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// The absolute URL of the project avatar.
+    pub avatar_url: Option<String>,
+}
+
+#[cfg(test)]
+impl Project {
+    /// A project with a non-empty repository for unit tests
+    pub fn stub(id: u32, path_with_namespace: &str) -> Self {
+        let (namespace, path) = path_with_namespace
+            .rsplit_once('/')
+            .unwrap_or(("", path_with_namespace));
+        Self {
+            id,
+            description: None,
+            empty_repo: false,
+            ssh_url_to_repo: format!("git@gitlab.example.com:{path_with_namespace}.git"),
+            http_url_to_repo: format!("https://gitlab.example.com/{path_with_namespace}.git"),
+            name: path.to_string(),
+            name_with_namespace: format!("{namespace} / {path}"),
+            path: path.to_string(),
+            path_with_namespace: path_with_namespace.to_string(),
+            last_activity_at: None,
+            lfs_enabled: false,
+            wiki_enabled: false,
+            visibility: None,
+            default_branch: Some("main".to_string()),
+            topics: Vec::new(),
+            avatar_url: None,
+        }
+    }
+}
+
+/// Visibility levels of projects and groups, ordered from the most restrictive one
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    Private,
+    Internal,
+    Public,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectExport {
+    /// One of `none`, `queued`, `started`, `finished`, `failed` or `regeneration_in_progress`.
+    pub export_status: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectImport {
+    pub id: u32,
+    /// One of `none`, `scheduled`, `started`, `finished` or `failed`.
+    pub import_status: String,
+    pub import_error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snippet {
+    pub id: u32,
+    pub title: String,
+    pub description: Option<String>,
+    /// One of `private`, `internal` or `public`.
+    pub visibility: String,
+    pub web_url: String,
+    /// The URL to clone the snippet repository over SSH.
+    pub ssh_url_to_repo: String,
+    /// The URL to clone the snippet repository over HTTPS.
+    pub http_url_to_repo: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Release {
+    pub tag_name: String,
+    pub assets: ReleaseAssets,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseAssets {
+    /// Links to files attached to the release, source archives are not included.
+    #[serde(default)]
+    pub links: Vec<ReleaseLink>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseLink {
+    pub name: String,
+    /// The URL of the file, it may be hosted outside of GitLab.
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Package {
+    pub id: u32,
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PackageFile {
+    pub file_name: String,
+    pub size: u64,
+    pub file_sha256: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WikiPage {
+    pub slug: String,
+    pub title: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -34,4 +157,5 @@ pub struct Group {
     pub path: String,
     pub description: Option<String>,
     pub full_path: String,
+    pub visibility: Option<Visibility>,
 }
@@ -0,0 +1,338 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use tracing::warn;
+use url::Url;
+
+use crate::gitlab::types;
+use crate::state::STATE_DIR;
+
+const JOURNAL_FILE: &str = "journal.jsonl";
+
+#[derive(Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Completed,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Entry {
+    Started {
+        at: DateTime<Utc>,
+    },
+    Project {
+        instance: String,
+        id: u32,
+        path_with_namespace: String,
+        outcome: Outcome,
+    },
+    GroupWiki {
+        instance: String,
+        group_path: String,
+        outcome: Outcome,
+    },
+    Finished {
+        at: DateTime<Utc>,
+    },
+}
+
+/// Units of work completed in the resumed run
+#[derive(Default)]
+struct Completed {
+    /// Projects by GitLab instance URL and project id
+    projects: HashSet<(String, u32)>,
+    /// Group wikis by GitLab instance URL and group path
+    group_wikis: HashSet<(String, String)>,
+}
+
+/// Keep the last outcome of a unit of work
+fn track<T: Eq + Hash>(
+    completed: &mut HashSet<T>,
+    failed: &mut HashSet<T>,
+    unit: T,
+    outcome: &Outcome,
+) {
+    if *outcome == Outcome::Completed {
+        failed.remove(&unit);
+        completed.insert(unit);
+    } else {
+        completed.remove(&unit);
+        failed.insert(unit);
+    }
+}
+
+/// Outcomes of projects and group wikis written while a run progresses,
+/// used to resume an interrupted run
+pub struct Journal {
+    file: File,
+    completed: Completed,
+}
+
+impl Journal {
+    fn path(dst: &str) -> PathBuf {
+        [dst, STATE_DIR, JOURNAL_FILE].iter().collect()
+    }
+
+    /// Start a journal of a new run, or continue the journal of the last run if `resume` is set
+    pub fn open(dst: &str, resume: bool) -> Result<Self> {
+        let path = Self::path(dst);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        if resume {
+            if let Some(completed) = Self::read_unfinished(&path)? {
+                let file = OpenOptions::new().append(true).open(&path)?;
+                return Ok(Self { file, completed });
+            }
+            warn!("There is no interrupted or failed run to resume, starting a new run");
+        }
+
+        let file = File::create(&path)?;
+        let mut journal = Self {
+            file,
+            completed: Completed::default(),
+        };
+        journal.write(&Entry::Started { at: Utc::now() })?;
+        Ok(journal)
+    }
+
+    /// Read completed units of the last run, if it was interrupted or has failed units
+    fn read_unfinished(path: &PathBuf) -> Result<Option<Completed>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut completed = Completed::default();
+        let mut failed = Completed::default();
+        let mut started = false;
+        let mut finished = false;
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            // the last line may be written partially when the run is interrupted
+            let Ok(entry) = serde_json::from_str::<Entry>(&line) else {
+                continue;
+            };
+            match entry {
+                Entry::Started { .. } => started = true,
+                Entry::Project {
+                    instance,
+                    id,
+                    outcome,
+                    ..
+                } => track(
+                    &mut completed.projects,
+                    &mut failed.projects,
+                    (instance, id),
+                    &outcome,
+                ),
+                Entry::GroupWiki {
+                    instance,
+                    group_path,
+                    outcome,
+                } => track(
+                    &mut completed.group_wikis,
+                    &mut failed.group_wikis,
+                    (instance, group_path),
+                    &outcome,
+                ),
+                Entry::Finished { .. } => finished = true,
+            }
+        }
+
+        if !started || (finished && failed.projects.is_empty() && failed.group_wikis.is_empty()) {
+            return Ok(None);
+        }
+
+        Ok(Some(completed))
+    }
+
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn is_completed(&self, instance: &Url, project: &types::Project) -> bool {
+        self.completed
+            .projects
+            .contains(&(instance.to_string(), project.id))
+    }
+
+    pub fn is_group_wiki_completed(&self, instance: &Url, group_path: &str) -> bool {
+        self.completed
+            .group_wikis
+            .contains(&(instance.to_string(), group_path.to_string()))
+    }
+
+    pub fn record(
+        &mut self,
+        instance: &Url,
+        project: &types::Project,
+        success: bool,
+    ) -> Result<()> {
+        self.write(&Entry::Project {
+            instance: instance.to_string(),
+            id: project.id,
+            path_with_namespace: project.path_with_namespace.clone(),
+            outcome: if success {
+                Outcome::Completed
+            } else {
+                Outcome::Failed
+            },
+        })
+    }
+
+    pub fn record_group_wiki(
+        &mut self,
+        instance: &Url,
+        group_path: &str,
+        success: bool,
+    ) -> Result<()> {
+        self.write(&Entry::GroupWiki {
+            instance: instance.to_string(),
+            group_path: group_path.to_string(),
+            outcome: if success {
+                Outcome::Completed
+            } else {
+                Outcome::Failed
+            },
+        })
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        self.write(&Entry::Finished { at: Utc::now() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dst(name: &str) -> String {
+        let dst =
+            std::env::temp_dir().join(format!("gitlobster-journal-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dst);
+        dst.to_str().unwrap().to_string()
+    }
+
+    fn instance() -> Url {
+        Url::parse("https://gitlab.example.com/").unwrap()
+    }
+
+    #[test]
+    fn resume_interrupted_run() {
+        let dst = dst("interrupted");
+        let (alpha, beta) = (
+            types::Project::stub(1, "team/alpha"),
+            types::Project::stub(2, "team/beta"),
+        );
+
+        let mut journal = Journal::open(&dst, false).unwrap();
+        journal.record(&instance(), &alpha, true).unwrap();
+        journal.record(&instance(), &beta, false).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&dst, true).unwrap();
+        assert!(journal.is_completed(&instance(), &alpha));
+        assert!(!journal.is_completed(&instance(), &beta));
+
+        let other = Url::parse("https://other.example.com/").unwrap();
+        assert!(!journal.is_completed(&other, &alpha));
+    }
+
+    #[test]
+    fn resume_failed_run() {
+        let dst = dst("failed");
+        let (alpha, beta) = (
+            types::Project::stub(1, "team/alpha"),
+            types::Project::stub(2, "team/beta"),
+        );
+
+        let mut journal = Journal::open(&dst, false).unwrap();
+        journal.record(&instance(), &alpha, true).unwrap();
+        journal.record(&instance(), &beta, false).unwrap();
+        journal.finish().unwrap();
+
+        // the resumed run keeps completed projects of all previous attempts
+        let mut journal = Journal::open(&dst, true).unwrap();
+        journal.record(&instance(), &beta, true).unwrap();
+        journal.record(&instance(), &alpha, false).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&dst, true).unwrap();
+        assert!(!journal.is_completed(&instance(), &alpha));
+        assert!(journal.is_completed(&instance(), &beta));
+    }
+
+    #[test]
+    fn nothing_to_resume_after_successful_run() {
+        let dst = dst("successful");
+        let alpha = types::Project::stub(1, "team/alpha");
+
+        let mut journal = Journal::open(&dst, false).unwrap();
+        journal.record(&instance(), &alpha, true).unwrap();
+        journal.finish().unwrap();
+
+        let journal = Journal::open(&dst, true).unwrap();
+        assert!(!journal.is_completed(&instance(), &alpha));
+    }
+
+    #[test]
+    fn partially_written_line() {
+        let dst = dst("partial");
+        let alpha = types::Project::stub(1, "team/alpha");
+
+        let mut journal = Journal::open(&dst, false).unwrap();
+        journal.record(&instance(), &alpha, true).unwrap();
+        journal.file.write_all(b"{\"event\":\"proj").unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&dst, true).unwrap();
+        assert!(journal.is_completed(&instance(), &alpha));
+    }
+
+    #[test]
+    fn resume_group_wikis() {
+        let dst = dst("group-wikis");
+        let alpha = types::Project::stub(1, "team/alpha");
+
+        let mut journal = Journal::open(&dst, false).unwrap();
+        journal.record(&instance(), &alpha, true).unwrap();
+        journal
+            .record_group_wiki(&instance(), "team", true)
+            .unwrap();
+        journal
+            .record_group_wiki(&instance(), "team/sub", false)
+            .unwrap();
+        journal.finish().unwrap();
+
+        // a failed group wiki makes the finished run resumable
+        let journal = Journal::open(&dst, true).unwrap();
+        assert!(journal.is_completed(&instance(), &alpha));
+        assert!(journal.is_group_wiki_completed(&instance(), "team"));
+        assert!(!journal.is_group_wiki_completed(&instance(), "team/sub"));
+    }
+
+    #[test]
+    fn new_run_without_resume() {
+        let dst = dst("new");
+        let alpha = types::Project::stub(1, "team/alpha");
+
+        let mut journal = Journal::open(&dst, false).unwrap();
+        journal.record(&instance(), &alpha, true).unwrap();
+        drop(journal);
+
+        Journal::open(&dst, false).unwrap();
+        let journal = Journal::open(&dst, true).unwrap();
+        assert!(!journal.is_completed(&instance(), &alpha));
+    }
+}
@@ -1,9 +1,29 @@
+mod assets;
+mod bundle;
 mod cli;
 mod cloner;
+mod config;
+mod export;
 mod git;
+mod gitea;
+mod github;
 mod gitlab;
-use anyhow::Result;
+mod journal;
+mod metadata;
+mod rate_limit;
+mod report;
+mod rest;
+mod retry;
+mod state;
+use cloner::Failure;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
-    cli::run()
+fn main() -> ExitCode {
+    match cli::run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(e.downcast_ref::<Failure>().map_or(1, Failure::exit_code))
+        }
+    }
 }
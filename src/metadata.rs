@@ -0,0 +1,160 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::gitlab::{self, types};
+use crate::state::STATE_DIR;
+
+const METADATA_DIR: &str = "metadata";
+const CURSOR_FILE: &str = "export.json";
+const LABELS_FILE: &str = "labels.json";
+
+/// Kinds of project items exported with their discussions, also used as folder names
+const ITEMS: [&str; 2] = ["issues", "merge_requests"];
+
+/// The time of the last successful export of a project
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    /// The time when the export was started, items updated after it are fetched next time
+    exported_at: DateTime<Utc>,
+}
+
+/// A folder of the project metadata in a tree parallel to local repositories
+pub fn path(dst: &str, project_dir: &str) -> PathBuf {
+    [dst, STATE_DIR, METADATA_DIR, project_dir].iter().collect()
+}
+
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, serde_json::to_string_pretty(value)?)?;
+    std::fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+fn read_cursor(dir: &Path) -> Result<Option<Cursor>> {
+    let path = dir.join(CURSOR_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&std::fs::read_to_string(path)?)?))
+}
+
+/// Export labels, issues and merge requests with their discussions to `dir`, one file per item.
+/// Only items updated since the last export are fetched
+pub async fn export(client: &gitlab::Client, project: &types::Project, dir: &Path) -> Result<()> {
+    let started_at = Utc::now();
+    let updated_after = read_cursor(dir)?.map(|c| c.exported_at);
+
+    let labels = client.get_labels(project.id).await?;
+    write_json(&dir.join(LABELS_FILE), &labels)?;
+
+    let mut exported = 0;
+    for kind in ITEMS {
+        let items = client
+            .get_updated_items(project.id, kind, updated_after)
+            .await?;
+        for mut item in items {
+            let Some(iid) = item.get("iid").and_then(Value::as_u64) else {
+                continue;
+            };
+            let discussions = client.get_discussions(project.id, kind, iid).await?;
+            item["discussions"] = Value::Array(discussions);
+            write_json(&dir.join(kind).join(format!("{iid}.json")), &item)?;
+            exported += 1;
+        }
+    }
+
+    write_json(
+        &dir.join(CURSOR_FILE),
+        &Cursor {
+            exported_at: started_at,
+        },
+    )?;
+    info!(
+        "metadata of {} exported: {exported} updated items",
+        project.path_with_namespace
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::tests::TempDir;
+    use chrono::SecondsFormat;
+    use mockito::Matcher;
+    use url::Url;
+
+    #[tokio::test]
+    async fn second_export_fetches_updated_items() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::parse(&format!("{}/", server.url())).unwrap();
+        let client = gitlab::Client::new("token", &url, None, true, None, None, None).unwrap();
+        let dir = TempDir::new("metadata-export");
+        let dir = PathBuf::from(dir.path("alpha"));
+        let project = types::Project::stub(1, "team/alpha");
+
+        server
+            .mock("GET", "/api/v4/projects/1/labels")
+            .match_query(Matcher::Any)
+            .with_body("[]")
+            .create_async()
+            .await;
+        server
+            .mock("GET", Matcher::Regex(r"/discussions$".to_string()))
+            .match_query(Matcher::Any)
+            .with_body("[]")
+            .create_async()
+            .await;
+        let mut first = Vec::new();
+        for kind in ITEMS {
+            let mock = server
+                .mock("GET", format!("/api/v4/projects/1/{kind}").as_str())
+                .match_query(Matcher::Any)
+                .with_body(r#"[{"iid": 1}]"#)
+                .expect(1)
+                .create_async()
+                .await;
+            first.push(mock);
+        }
+        export(&client, &project, &dir).await.unwrap();
+        let cursor = read_cursor(&dir).unwrap().unwrap().exported_at;
+        for mock in first {
+            mock.assert_async().await;
+            mock.remove_async().await;
+        }
+
+        // the second export requests only items updated after the first one
+        let updated_after = cursor.to_rfc3339_opts(SecondsFormat::Secs, true);
+        let mut second = Vec::new();
+        for kind in ITEMS {
+            let mock = server
+                .mock("GET", format!("/api/v4/projects/1/{kind}").as_str())
+                .match_query(Matcher::UrlEncoded(
+                    "updated_after".into(),
+                    updated_after.clone(),
+                ))
+                .with_body(r#"[{"iid": 2}]"#)
+                .expect(1)
+                .create_async()
+                .await;
+            second.push(mock);
+        }
+        export(&client, &project, &dir).await.unwrap();
+        for mock in second {
+            mock.assert_async().await;
+        }
+
+        assert!(read_cursor(&dir).unwrap().unwrap().exported_at > cursor);
+        for kind in ITEMS {
+            assert!(dir.join(kind).join("1.json").exists());
+            assert!(dir.join(kind).join("2.json").exists());
+        }
+    }
+}
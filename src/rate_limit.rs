@@ -0,0 +1,209 @@
+use chrono::{DateTime, Utc};
+use reqwest::{header::HeaderMap, Response, StatusCode};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+use tracing::warn;
+
+#[derive(Default)]
+struct Slots {
+    /// The earliest time of the next request by the requests per second limit
+    next_request: Option<Instant>,
+    /// Requests are paused until this time after the API reported the exhausted limit
+    paused_until: Option<Instant>,
+}
+
+/// Throttles requests of all concurrent tasks to one GitLab or GitHub instance
+pub struct RateLimiter {
+    interval: Option<Duration>,
+    slots: Mutex<Slots>,
+    /// Limits concurrent requests
+    requests: Semaphore,
+}
+
+impl RateLimiter {
+    /// `rate` is a maximum of requests per second and `concurrency` is a maximum of
+    /// concurrent requests, requests are not limited without them
+    pub fn new(rate: Option<f64>, concurrency: Option<usize>) -> Self {
+        Self {
+            interval: rate.map(|r| Duration::from_secs_f64(1.0 / r)),
+            slots: Mutex::new(Slots::default()),
+            requests: Semaphore::new(concurrency.unwrap_or(Semaphore::MAX_PERMITS)),
+        }
+    }
+
+    /// Wait for a time slot of the next request, the request must be sent while
+    /// the returned permit is alive
+    pub async fn wait(&self) -> SemaphorePermit<'_> {
+        let permit = self
+            .requests
+            .acquire()
+            .await
+            .expect("rate limiter semaphore is never closed");
+        let start = {
+            let mut slots = self.slots.lock().expect("poisoned rate limiter");
+            let now = Instant::now();
+            let mut start = slots.next_request.map_or(now, |t| t.max(now));
+            if let Some(interval) = self.interval {
+                slots.next_request = Some(start + interval);
+            }
+            if let Some(paused_until) = slots.paused_until {
+                start = start.max(paused_until);
+            }
+            start
+        };
+        tokio::time::sleep_until(start).await;
+        permit
+    }
+
+    /// Pause requests if the response reports the exhausted rate limit
+    pub fn update(&self, resp: &Response) {
+        let headers = resp.headers();
+        let pause = if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+            retry_after(headers).or_else(|| reset_after(headers))
+        } else if rate_limit_header(headers, "remaining") == Some("0") {
+            reset_after(headers)
+        } else {
+            None
+        };
+
+        let Some(pause) = pause.filter(|p| !p.is_zero()) else {
+            return;
+        };
+        warn!(
+            "API rate limit reached, requests are paused for {:.1}s",
+            pause.as_secs_f64()
+        );
+        let until = Instant::now() + pause;
+        let mut slots = self.slots.lock().expect("poisoned rate limiter");
+        slots.paused_until = Some(slots.paused_until.map_or(until, |t| t.max(until)));
+    }
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|h| h.to_str().ok())
+}
+
+/// GitLab sends `RateLimit-*` headers and GitHub sends `X-RateLimit-*` ones
+fn rate_limit_header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    header(headers, &format!("ratelimit-{name}"))
+        .or_else(|| header(headers, &format!("x-ratelimit-{name}")))
+}
+
+/// `Retry-After` contains seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = header(headers, "retry-after")?;
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+/// `RateLimit-Reset` contains a Unix time when the limit is reset
+fn reset_after(headers: &HeaderMap) -> Option<Duration> {
+    let reset = rate_limit_header(headers, "reset")?.parse::<i64>().ok()?;
+    let secs = u64::try_from(reset - Utc::now().timestamp()).ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn secs(d: Option<Duration>) -> Option<u64> {
+        // one second margin for the time spent by the test itself
+        d.map(|d| (d + Duration::from_millis(500)).as_secs())
+    }
+
+    #[test]
+    fn retry_after_seconds() {
+        let h = headers(&[("retry-after", "30")]);
+        assert_eq!(retry_after(&h), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn retry_after_date() {
+        let date = (Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let h = headers(&[("retry-after", &date)]);
+        assert!(matches!(secs(retry_after(&h)), Some(59 | 60)));
+
+        let past = (Utc::now() - chrono::Duration::seconds(60)).to_rfc2822();
+        assert_eq!(retry_after(&headers(&[("retry-after", &past)])), None);
+        assert_eq!(retry_after(&headers(&[("retry-after", "soon")])), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn reset_time() {
+        let reset = (Utc::now().timestamp() + 120).to_string();
+        assert!(matches!(
+            secs(reset_after(&headers(&[("ratelimit-reset", &reset)]))),
+            Some(119 | 120)
+        ));
+        assert!(matches!(
+            secs(reset_after(&headers(&[("x-ratelimit-reset", &reset)]))),
+            Some(119 | 120)
+        ));
+
+        let past = (Utc::now().timestamp() - 120).to_string();
+        assert_eq!(reset_after(&headers(&[("ratelimit-reset", &past)])), None);
+    }
+
+    #[test]
+    fn gitlab_and_github_headers() {
+        let gitlab = headers(&[("ratelimit-remaining", "0")]);
+        let github = headers(&[("x-ratelimit-remaining", "10")]);
+        assert_eq!(rate_limit_header(&gitlab, "remaining"), Some("0"));
+        assert_eq!(rate_limit_header(&github, "remaining"), Some("10"));
+        assert_eq!(rate_limit_header(&github, "reset"), None);
+    }
+
+    async fn paused_until(status: usize, pairs: &[(&str, &str)]) -> Option<Instant> {
+        let mut server = mockito::Server::new_async().await;
+        let mut mock = server.mock("GET", "/").with_status(status);
+        for (name, value) in pairs {
+            mock = mock.with_header(*name, value);
+        }
+        let _mock = mock.create_async().await;
+
+        let limiter = RateLimiter::new(None, None);
+        limiter.update(&reqwest::get(server.url()).await.unwrap());
+        let paused_until = limiter.slots.lock().unwrap().paused_until;
+        paused_until
+    }
+
+    #[tokio::test]
+    async fn pause_on_exhausted_limit() {
+        let reset = (Utc::now().timestamp() + 60).to_string();
+
+        assert!(paused_until(429, &[("retry-after", "30")]).await.is_some());
+        assert!(paused_until(429, &[("ratelimit-reset", &reset)])
+            .await
+            .is_some());
+        assert!(paused_until(
+            200,
+            &[("ratelimit-remaining", "0"), ("ratelimit-reset", &reset)]
+        )
+        .await
+        .is_some());
+
+        assert!(paused_until(
+            200,
+            &[("ratelimit-remaining", "5"), ("ratelimit-reset", &reset)]
+        )
+        .await
+        .is_none());
+        assert!(paused_until(503, &[("retry-after", "30")]).await.is_none());
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+use url::Url;
+
+use crate::gitlab::types;
+
+/// What was done with a project during the run
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// The repository was cloned for the first time
+    Cloned,
+    /// The existing local repository was updated
+    Updated,
+    /// The repository was fetched and pushed to all backup targets
+    Pushed,
+    /// The project was not synced because it is up to date or completed in the resumed run
+    Skipped,
+    /// The project was excluded by patterns or the limit
+    Filtered,
+    /// Syncing of the project has failed
+    Failed,
+    /// The project was not started, because the run was stopped by an error of another one
+    NotStarted,
+}
+
+/// A successful result of a project sync
+pub struct Synced {
+    pub action: Action,
+    /// Growth of the local repositories on disk, if known. It is not the amount of bytes
+    /// transferred: packs are compressed on disk and repacking may even shrink them
+    pub bytes: Option<u64>,
+    /// Size of LFS objects of the project, if they are synced
+    pub lfs_bytes: Option<u64>,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+#[derive(Serialize)]
+struct ProjectReport {
+    instance: String,
+    /// Group wikis have no project id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u32>,
+    path_with_namespace: String,
+    /// The entry is the wiki of the group at `path_with_namespace`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    group_wiki: bool,
+    action: Action,
+    duration_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lfs_bytes: Option<u64>,
+    /// Retries of failed requests and git commands
+    #[serde(skip_serializing_if = "is_zero")]
+    retries: u32,
+    /// The error with its causes, from the outermost one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+}
+
+#[derive(Serialize, Default)]
+struct Totals {
+    projects: usize,
+    cloned: usize,
+    updated: usize,
+    pushed: usize,
+    skipped: usize,
+    filtered: usize,
+    failed: usize,
+    not_started: usize,
+    bytes: u64,
+    lfs_bytes: u64,
+    retries: u32,
+}
+
+#[derive(Serialize)]
+struct ReportFile<'a> {
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    duration_secs: f64,
+    totals: Totals,
+    projects: &'a [ProjectReport],
+}
+
+/// A machine-readable report of a run, written by the `--report` flag
+pub struct Report {
+    started_at: DateTime<Utc>,
+    projects: Vec<ProjectReport>,
+}
+
+impl Report {
+    pub fn start() -> Self {
+        Self {
+            started_at: Utc::now(),
+            projects: Vec::new(),
+        }
+    }
+
+    fn entry(instance: &Url, project: &types::Project, action: Action) -> ProjectReport {
+        ProjectReport {
+            instance: instance.to_string(),
+            id: Some(project.id),
+            path_with_namespace: project.path_with_namespace.clone(),
+            group_wiki: false,
+            action,
+            duration_secs: 0.0,
+            bytes: None,
+            lfs_bytes: None,
+            retries: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Add a project which was not synced in the run
+    pub fn add_unsynced(&mut self, instance: &Url, project: &types::Project, action: Action) {
+        self.projects.push(Self::entry(instance, project, action));
+    }
+
+    pub fn add(
+        &mut self,
+        instance: &Url,
+        project: &types::Project,
+        result: &Result<Synced>,
+        duration: Duration,
+        retries: u32,
+    ) {
+        let mut entry = match result {
+            Ok(synced) => ProjectReport {
+                bytes: synced.bytes,
+                lfs_bytes: synced.lfs_bytes,
+                ..Self::entry(instance, project, synced.action)
+            },
+            Err(e) => ProjectReport {
+                errors: e.chain().map(ToString::to_string).collect(),
+                ..Self::entry(instance, project, Action::Failed)
+            },
+        };
+        entry.duration_secs = duration.as_secs_f64();
+        entry.retries = retries;
+        self.projects.push(entry);
+    }
+
+    pub fn add_group_wiki(
+        &mut self,
+        instance: &Url,
+        full_path: &str,
+        result: &Result<Action>,
+        duration: Duration,
+        retries: u32,
+    ) {
+        let (action, errors) = match result {
+            Ok(action) => (*action, Vec::new()),
+            Err(e) => (Action::Failed, e.chain().map(ToString::to_string).collect()),
+        };
+        self.projects.push(ProjectReport {
+            instance: instance.to_string(),
+            id: None,
+            path_with_namespace: full_path.to_string(),
+            group_wiki: true,
+            action,
+            duration_secs: duration.as_secs_f64(),
+            bytes: None,
+            lfs_bytes: None,
+            retries,
+            errors,
+        });
+    }
+
+    fn totals(&self) -> Totals {
+        let mut totals = Totals {
+            projects: self.projects.len(),
+            ..Default::default()
+        };
+        for project in &self.projects {
+            let counter = match project.action {
+                Action::Cloned => &mut totals.cloned,
+                Action::Updated => &mut totals.updated,
+                Action::Pushed => &mut totals.pushed,
+                Action::Skipped => &mut totals.skipped,
+                Action::Filtered => &mut totals.filtered,
+                Action::Failed => &mut totals.failed,
+                Action::NotStarted => &mut totals.not_started,
+            };
+            *counter += 1;
+            totals.bytes += project.bytes.unwrap_or_default();
+            totals.lfs_bytes += project.lfs_bytes.unwrap_or_default();
+            totals.retries += project.retries;
+        }
+        totals
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let finished_at = Utc::now();
+        let report = ReportFile {
+            started_at: self.started_at,
+            finished_at,
+            duration_secs: (finished_at - self.started_at)
+                .to_std()
+                .unwrap_or_default()
+                .as_secs_f64(),
+            totals: self.totals(),
+            projects: &self.projects,
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn group_wikis_in_totals() {
+        let url = Url::parse("https://gitlab.com/").unwrap();
+        let mut report = Report::start();
+        let synced = Synced {
+            action: Action::Cloned,
+            bytes: Some(10),
+            lfs_bytes: None,
+        };
+        let project = types::Project::stub(1, "team/alpha");
+        report.add(&url, &project, &Ok(synced), Duration::ZERO, 0);
+        report.add_group_wiki(&url, "team", &Ok(Action::Pushed), Duration::ZERO, 1);
+        report.add_group_wiki(&url, "other", &Err(anyhow!("denied")), Duration::ZERO, 0);
+        let project = types::Project::stub(2, "team/beta");
+        report.add_unsynced(&url, &project, Action::NotStarted);
+
+        let totals = report.totals();
+        assert_eq!(
+            (totals.projects, totals.cloned, totals.pushed, totals.failed),
+            (4, 1, 1, 1)
+        );
+        assert_eq!(totals.not_started, 1);
+        assert_eq!(totals.retries, 1);
+        let wiki = serde_json::to_value(&report.projects[2]).unwrap();
+        assert_eq!(wiki["group_wiki"], true);
+        assert_eq!(wiki["errors"][0], "denied");
+        assert!(wiki.get("id").is_none());
+    }
+}
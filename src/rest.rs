@@ -0,0 +1,268 @@
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
+use tracing::info;
+use url::Url;
+
+use crate::gitlab;
+use crate::rate_limit::RateLimiter;
+use crate::retry;
+
+/// Fields of GitHub and Gitea repositories used to push them
+pub trait Repository: DeserializeOwned {
+    /// The name of the repository with its owner
+    fn full_name(&self) -> &str;
+    fn clone_url(&self) -> &str;
+    fn ssh_url(&self) -> &str;
+    fn default_branch(&self) -> Option<&str>;
+}
+
+/// A client of GitHub-like REST APIs, GitHub and Gitea share pagination, errors
+/// and repository endpoints, they differ in headers and the page size parameter
+pub struct Client {
+    url: Url,
+    http: reqwest::Client,
+    disable_sync_date: bool,
+    /// The query parameter of the page size and items per page of lists
+    page_size: (&'static str, u32),
+    rate_limiter: RateLimiter,
+}
+
+impl Client {
+    /// `headers` are sent with every request, they carry the token
+    pub fn new(
+        url: Url,
+        headers: HeaderMap,
+        timeout: Option<u32>,
+        page_size: (&'static str, u32),
+        disable_sync_date: bool,
+        rate_limiter: RateLimiter,
+    ) -> Result<Self> {
+        let mut http = reqwest::ClientBuilder::new().default_headers(headers);
+        if let Some(timeout) = timeout {
+            http = http.timeout(Duration::from_secs(timeout.into()));
+        }
+
+        Ok(Client {
+            url,
+            http: http.build()?,
+            disable_sync_date,
+            page_size,
+            rate_limiter,
+        })
+    }
+
+    fn build_request<J: Serialize>(
+        &self,
+        m: Method,
+        path: &str,
+        json: Option<J>,
+    ) -> RequestBuilder {
+        let url = self.url.join(path).unwrap();
+        info!("{}", url);
+
+        let mut req = self.http.request(m, url);
+        if let Some(json) = json {
+            req = req.json(&json);
+        }
+
+        req
+    }
+
+    /// Send a request with retries and rate limiting
+    pub async fn request<J: Serialize>(
+        &self,
+        m: Method,
+        path: &str,
+        json: Option<J>,
+    ) -> reqwest::Result<Response> {
+        retry::run(
+            &format!("{m} {path}"),
+            || async {
+                let _permit = self.rate_limiter.wait().await;
+                let resp = self
+                    .build_request(m.clone(), path, json.as_ref())
+                    .send()
+                    .await?;
+                self.rate_limiter.update(&resp);
+                resp.error_for_status()
+            },
+            retry::Policy::is_retryable_http,
+        )
+        .await
+    }
+
+    /// Send a request and parse its JSON response
+    pub async fn send<J: Serialize, T: DeserializeOwned>(
+        &self,
+        m: Method,
+        path: &str,
+        json: Option<J>,
+    ) -> reqwest::Result<T> {
+        self.request(m, path, json).await?.json::<T>().await
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> reqwest::Result<T> {
+        self.send(Method::GET, path, None::<()>).await
+    }
+
+    /// Get an item, missing items are `None`
+    pub async fn get_optional<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> reqwest::Result<Option<T>> {
+        match self.get(path).await {
+            Ok(item) => Ok(Some(item)),
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get all pages of a list until an empty page, servers may cap pages below the limit,
+    /// like Gitea does with its maximum page size of 50 by default
+    pub async fn get_all_pages<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &str,
+    ) -> reqwest::Result<Vec<T>> {
+        let (param, limit) = self.page_size;
+        let mut items: Vec<T> = vec![];
+        let mut page = 1;
+        loop {
+            let mut page_path = format!("{path}?{param}={limit}&page={page}");
+            if !query.is_empty() {
+                page_path = format!("{page_path}&{query}");
+            }
+            let mut page_items: Vec<T> = self.get(&page_path).await?;
+            if page_items.is_empty() {
+                return Ok(items);
+            }
+            items.append(&mut page_items);
+            page += 1;
+        }
+    }
+
+    fn make_description(&self, description: Option<&String>) -> String {
+        let description = description.cloned().unwrap_or_default();
+        if self.disable_sync_date {
+            description
+        } else {
+            format!("{description} 🦞 Synced: {}", Utc::now().to_rfc3339())
+        }
+    }
+
+    pub async fn repo_exist<R: Repository>(
+        &self,
+        owner: &str,
+        name: &str,
+    ) -> reqwest::Result<Option<R>> {
+        self.get_optional(&format!("repos/{owner}/{name}")).await
+    }
+
+    /// Create a repository in the organization or in the account of the token owner
+    pub async fn make_repo<R: Repository>(
+        &self,
+        org: Option<&str>,
+        name: &str,
+        info: &gitlab::types::Project,
+        private: bool,
+    ) -> reqwest::Result<R> {
+        #[derive(Serialize)]
+        struct MakeRepoRequest<'a> {
+            name: &'a str,
+            description: String,
+            private: bool,
+        }
+
+        let data = MakeRepoRequest {
+            name,
+            description: self.make_description(info.description.as_ref()),
+            private,
+        };
+        let path = match org {
+            Some(org) => format!("orgs/{org}/repos"),
+            None => "user/repos".to_string(),
+        };
+        self.send(Method::POST, &path, Some(data)).await
+    }
+
+    pub async fn update_repo<R: Repository>(
+        &self,
+        repo: &R,
+        info: &gitlab::types::Project,
+        private: bool,
+    ) -> reqwest::Result<R> {
+        #[derive(Serialize)]
+        struct UpdateRepoRequest {
+            description: String,
+            private: bool,
+        }
+
+        let data = UpdateRepoRequest {
+            description: self.make_description(info.description.as_ref()),
+            private,
+        };
+        self.send(
+            Method::PATCH,
+            &format!("repos/{}", repo.full_name()),
+            Some(data),
+        )
+        .await
+    }
+
+    /// The branch must already exist in the repository
+    pub async fn update_default_branch<R: Repository>(
+        &self,
+        repo: &R,
+        branch: &str,
+    ) -> reqwest::Result<R> {
+        #[derive(Serialize)]
+        struct UpdateDefaultBranchRequest<'a> {
+            default_branch: &'a str,
+        }
+
+        self.send(
+            Method::PATCH,
+            &format!("repos/{}", repo.full_name()),
+            Some(UpdateDefaultBranchRequest {
+                default_branch: branch,
+            }),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    #[tokio::test]
+    async fn pages_capped_below_limit() {
+        let mut server = mockito::Server::new_async().await;
+        let url = Url::parse(&format!("{}/", server.url())).unwrap();
+        let limiter = RateLimiter::new(None, None);
+        let client =
+            Client::new(url, HeaderMap::new(), None, ("limit", 100), true, limiter).unwrap();
+        // the server returns at most two items whatever limit is requested
+        for (page, body) in [(1, "[1, 2]"), (2, "[3, 4]"), (3, "[5]"), (4, "[]")] {
+            server
+                .mock("GET", "/items")
+                .match_query(Matcher::AllOf(vec![
+                    Matcher::UrlEncoded("limit".into(), "100".into()),
+                    Matcher::UrlEncoded("page".into(), page.to_string()),
+                ]))
+                .with_body(body)
+                .expect(1)
+                .create_async()
+                .await;
+        }
+
+        let items: Vec<u32> = client.get_all_pages("items", "").await.unwrap();
+        assert_eq!(items, [1, 2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,270 @@
+use anyhow::Result;
+use rand::Rng;
+use regex::Regex;
+use std::cell::Cell;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::warn;
+
+/// HTTP statuses of transient GitLab errors
+const DEFAULT_STATUSES: [u16; 5] = [429, 500, 502, 503, 504];
+
+/// Patterns of transient network errors in git output
+const DEFAULT_GIT_ERRORS: [&str; 8] = [
+    "Could not resolve host",
+    "Connection timed out",
+    "Connection reset",
+    "Operation timed out",
+    "The remote end hung up unexpectedly",
+    "early EOF",
+    "RPC failed",
+    "returned error: 50[0234]",
+];
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+tokio::task_local! {
+    static RETRIES: Cell<u32>;
+}
+
+/// Which failed requests and git commands are repeated and how long to wait between attempts
+pub struct Policy {
+    /// Maximum attempts of one operation, including the first one
+    pub attempts: u32,
+    /// A delay before the first retry, doubled for each next one
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// Randomize delays, so concurrent operations do not retry at the same time
+    pub jitter: bool,
+    pub statuses: Vec<u16>,
+    pub git_errors: Vec<Regex>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            jitter: true,
+            statuses: DEFAULT_STATUSES.to_vec(),
+            git_errors: Self::git_errors(&DEFAULT_GIT_ERRORS).expect("invalid default pattern"),
+        }
+    }
+}
+
+impl Policy {
+    pub fn git_errors<S: AsRef<str>>(patterns: &[S]) -> Result<Vec<Regex>> {
+        Ok(patterns
+            .iter()
+            .map(|p| Regex::new(p.as_ref()))
+            .collect::<Result<_, _>>()?)
+    }
+
+    pub fn is_retryable_http(&self, e: &reqwest::Error) -> bool {
+        match e.status() {
+            Some(status) => self.statuses.contains(&status.as_u16()),
+            None => e.is_timeout() || e.is_connect(),
+        }
+    }
+
+    pub fn is_retryable_git(&self, e: &anyhow::Error) -> bool {
+        let msg = e.to_string();
+        self.git_errors.iter().any(|p| p.is_match(&msg))
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .backoff
+            .saturating_mul(2_u32.saturating_pow(attempt - 1))
+            .min(self.max_backoff);
+        if !self.jitter {
+            return delay;
+        }
+        // a random factor from 0.5 to 1
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+/// Set the policy for the whole run, the default one is used if it is not set
+pub fn init(policy: Policy) {
+    let _ = POLICY.set(policy);
+}
+
+pub fn policy() -> &'static Policy {
+    POLICY.get_or_init(Policy::default)
+}
+
+/// Run an operation, repeating it while it fails with a retryable error
+pub async fn run<T, E, F, Fut>(
+    name: &str,
+    mut operation: F,
+    is_retryable: impl Fn(&Policy, &E) -> bool,
+) -> Result<T, E>
+where
+    E: Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let policy = policy();
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if attempt < policy.attempts && is_retryable(policy, &e) => {
+                let delay = policy.delay(attempt);
+                warn!(
+                    "{name} failed (attempt {attempt} of {}), retrying in {:.1}s: {e}",
+                    policy.attempts,
+                    delay.as_secs_f64()
+                );
+                let _ = RETRIES.try_with(|r| r.set(r.get() + 1));
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Run a future and count retries made inside it
+pub async fn counted<F: Future>(future: F) -> (F::Output, u32) {
+    RETRIES
+        .scope(Cell::new(0), async {
+            let output = future.await;
+            (output, RETRIES.with(Cell::get))
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn http_error(server: &mut mockito::Server, status: usize) -> reqwest::Error {
+        let _mock = server
+            .mock("GET", "/")
+            .with_status(status)
+            .create_async()
+            .await;
+        reqwest::get(server.url())
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn retryable_http_statuses() {
+        let mut server = mockito::Server::new_async().await;
+        let policy = Policy::default();
+
+        assert!(policy.is_retryable_http(&http_error(&mut server, 503).await));
+        assert!(policy.is_retryable_http(&http_error(&mut server, 429).await));
+        assert!(!policy.is_retryable_http(&http_error(&mut server, 404).await));
+        assert!(!policy.is_retryable_http(&http_error(&mut server, 401).await));
+
+        let policy = Policy {
+            statuses: vec![404],
+            ..Policy::default()
+        };
+        assert!(policy.is_retryable_http(&http_error(&mut server, 404).await));
+        assert!(!policy.is_retryable_http(&http_error(&mut server, 503).await));
+    }
+
+    #[tokio::test]
+    async fn retryable_connection_errors() {
+        // nothing listens on the discard port
+        let e = reqwest::get("http://127.0.0.1:9/").await.unwrap_err();
+        assert!(Policy::default().is_retryable_http(&e));
+    }
+
+    #[test]
+    fn retryable_git_errors() {
+        let policy = Policy::default();
+        let err = |msg: &str| anyhow::anyhow!("git error: {msg}");
+
+        assert!(policy.is_retryable_git(&err(
+            "fatal: unable to access 'https://gitlab.com/a/b.git/': Could not resolve host: gitlab.com"
+        )));
+        assert!(policy.is_retryable_git(&err(
+            "error: RPC failed; HTTP 502 curl 22 The requested URL returned error: 502"
+        )));
+        assert!(!policy.is_retryable_git(&err(
+            "remote: HTTP Basic: Access denied\nfatal: Authentication failed"
+        )));
+        assert!(!policy.is_retryable_git(&err("fatal: couldn't find remote ref refs/heads/master")));
+
+        let policy = Policy {
+            git_errors: Policy::git_errors(&["Access denied"]).unwrap(),
+            ..Policy::default()
+        };
+        assert!(policy.is_retryable_git(&err("remote: HTTP Basic: Access denied")));
+        assert!(!policy.is_retryable_git(&err("Could not resolve host: gitlab.com")));
+    }
+
+    #[test]
+    fn invalid_git_error_pattern() {
+        assert!(Policy::git_errors(&["(unclosed"]).is_err());
+    }
+
+    #[test]
+    fn exponential_delays() {
+        let policy = Policy {
+            backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(10),
+            jitter: false,
+            ..Policy::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(2));
+        assert_eq!(policy.delay(2), Duration::from_secs(4));
+        assert_eq!(policy.delay(3), Duration::from_secs(8));
+        assert_eq!(policy.delay(4), Duration::from_secs(10));
+        assert_eq!(policy.delay(40), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn jittered_delays() {
+        let policy = Policy {
+            backoff: Duration::from_secs(4),
+            ..Policy::default()
+        };
+        for _ in 0..100 {
+            let delay = policy.delay(1);
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+        }
+    }
+
+    #[tokio::test]
+    async fn counted_retries() {
+        let mut calls = 0;
+        let (result, retries) = counted(run(
+            "op",
+            || {
+                calls += 1;
+                let calls = calls;
+                async move {
+                    if calls < 2 {
+                        Err("transient")
+                    } else {
+                        Ok(calls)
+                    }
+                }
+            },
+            |_, e: &&str| *e == "transient",
+        ))
+        .await;
+        assert_eq!(result, Ok(2));
+        assert_eq!(retries, 1);
+
+        let (result, retries) = counted(run(
+            "op",
+            || async { Err::<(), _>("permanent") },
+            |_, e: &&str| *e == "transient",
+        ))
+        .await;
+        assert_eq!(result, Err("permanent"));
+        assert_eq!(retries, 0);
+    }
+}
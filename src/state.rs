@@ -0,0 +1,319 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use url::Url;
+
+use crate::gitlab::types;
+
+/// A folder in dst for files of gitlobster itself
+pub const STATE_DIR: &str = ".gitlobster";
+const STATE_FILE: &str = "state.json";
+
+/// GitLab updates `last_activity_at` of a project not more often than once per hour
+const ACTIVITY_UPDATE_PERIOD: i64 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct ProjectState {
+    path_with_namespace: String,
+    last_activity_at: DateTime<Utc>,
+    /// The time when fetching of the project was started
+    synced_at: DateTime<Utc>,
+    /// Backup targets which got the project during the sync
+    backups: Vec<String>,
+    /// Sync options which added content to the project copies, like wikis or LFS objects
+    #[serde(default)]
+    options: Vec<String>,
+}
+
+/// Groups have no activity time, so their wikis are compared by refs
+#[derive(Serialize, Deserialize)]
+struct GroupWikiState {
+    /// Object ids of refs of the wiki repository by their names
+    refs: BTreeMap<String, String>,
+    synced_at: DateTime<Utc>,
+    /// Backup targets which got the wiki during the sync
+    backups: Vec<String>,
+}
+
+/// Last successful syncs of projects, saved between runs
+#[derive(Serialize, Deserialize, Default)]
+pub struct State {
+    /// Projects states by GitLab instance URL and project id
+    projects: BTreeMap<String, BTreeMap<u32, ProjectState>>,
+    /// Group wikis states by GitLab instance URL and group path
+    #[serde(default)]
+    group_wikis: BTreeMap<String, BTreeMap<String, GroupWikiState>>,
+}
+
+impl State {
+    fn path(dst: &str) -> PathBuf {
+        [dst, STATE_DIR, STATE_FILE].iter().collect()
+    }
+
+    /// Load the state from dst, a missing state file means the first run
+    pub fn load(dst: &str) -> Result<Self> {
+        let path = Self::path(dst);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, dst: &str) -> Result<()> {
+        let path = Self::path(dst);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Check that the project has no activity since its last sync to the same backup targets
+    /// with the same options, options disabled since the last sync need no new sync
+    pub fn is_up_to_date(
+        &self,
+        instance: &Url,
+        project: &types::Project,
+        backups: &[String],
+        options: &[String],
+    ) -> bool {
+        let Some(last_activity_at) = project.last_activity_at else {
+            return false;
+        };
+        let Some(state) = self
+            .projects
+            .get(instance.as_str())
+            .and_then(|p| p.get(&project.id))
+        else {
+            return false;
+        };
+
+        // a push soon after the previous activity may not change `last_activity_at`,
+        // so only syncs made after the update period are trusted
+        let trusted_sync =
+            state.synced_at >= last_activity_at + Duration::hours(ACTIVITY_UPDATE_PERIOD);
+
+        last_activity_at <= state.last_activity_at
+            && trusted_sync
+            && backups.iter().all(|b| state.backups.contains(b))
+            && options.iter().all(|o| state.options.contains(o))
+    }
+
+    pub fn record(
+        &mut self,
+        instance: &Url,
+        project: &types::Project,
+        backups: Vec<String>,
+        options: Vec<String>,
+        synced_at: DateTime<Utc>,
+    ) {
+        let Some(last_activity_at) = project.last_activity_at else {
+            return;
+        };
+        self.projects
+            .entry(instance.to_string())
+            .or_default()
+            .insert(
+                project.id,
+                ProjectState {
+                    path_with_namespace: project.path_with_namespace.clone(),
+                    last_activity_at,
+                    synced_at,
+                    backups,
+                    options,
+                },
+            );
+    }
+
+    /// Check that the group wiki has the same refs as on its last sync to the same backup targets
+    pub fn is_group_wiki_up_to_date(
+        &self,
+        instance: &Url,
+        group_path: &str,
+        refs: &BTreeMap<String, String>,
+        backups: &[String],
+    ) -> bool {
+        self.group_wikis
+            .get(instance.as_str())
+            .and_then(|w| w.get(group_path))
+            .is_some_and(|state| {
+                &state.refs == refs && backups.iter().all(|b| state.backups.contains(b))
+            })
+    }
+
+    pub fn record_group_wiki(
+        &mut self,
+        instance: &Url,
+        group_path: &str,
+        refs: BTreeMap<String, String>,
+        backups: Vec<String>,
+        synced_at: DateTime<Utc>,
+    ) {
+        self.group_wikis
+            .entry(instance.to_string())
+            .or_default()
+            .insert(
+                group_path.to_string(),
+                GroupWikiState {
+                    refs,
+                    synced_at,
+                    backups,
+                },
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn project(last_activity_at: &str) -> types::Project {
+        let mut project = types::Project::stub(7, "team/alpha");
+        project.last_activity_at = Some(time(last_activity_at));
+        project
+    }
+
+    fn instance() -> Url {
+        Url::parse("https://gitlab.com/").unwrap()
+    }
+
+    #[test]
+    fn unknown_project_is_not_up_to_date() {
+        let state = State::default();
+        assert!(!state.is_up_to_date(&instance(), &project("2024-01-01T10:00:00Z"), &[], &[]));
+    }
+
+    #[test]
+    fn sync_after_update_period() {
+        let mut state = State::default();
+        let pr = project("2024-01-01T10:00:00Z");
+        state.record(
+            &instance(),
+            &pr,
+            Vec::new(),
+            Vec::new(),
+            time("2024-01-01T11:00:00Z"),
+        );
+
+        assert!(state.is_up_to_date(&instance(), &pr, &[], &[]));
+        assert!(!state.is_up_to_date(&instance(), &project("2024-01-01T10:30:00Z"), &[], &[]));
+    }
+
+    #[test]
+    fn sync_within_update_period() {
+        let mut state = State::default();
+        let pr = project("2024-01-01T10:00:00Z");
+        state.record(
+            &instance(),
+            &pr,
+            Vec::new(),
+            Vec::new(),
+            time("2024-01-01T10:59:59Z"),
+        );
+
+        // a later push may not have changed last_activity_at yet
+        assert!(!state.is_up_to_date(&instance(), &pr, &[], &[]));
+    }
+
+    #[test]
+    fn new_backup_target() {
+        let mut state = State::default();
+        let pr = project("2024-01-01T10:00:00Z");
+        let backup = "https://backup.example.com/".to_string();
+        state.record(
+            &instance(),
+            &pr,
+            vec![backup.clone()],
+            Vec::new(),
+            time("2024-01-02T00:00:00Z"),
+        );
+
+        assert!(state.is_up_to_date(&instance(), &pr, &[backup.clone()], &[]));
+        assert!(!state.is_up_to_date(
+            &instance(),
+            &pr,
+            &[backup, "https://other.example.com/".to_string()],
+            &[]
+        ));
+    }
+
+    #[test]
+    fn new_sync_options() {
+        let mut state = State::default();
+        let pr = project("2024-01-01T10:00:00Z");
+        let lfs = "lfs".to_string();
+        state.record(
+            &instance(),
+            &pr,
+            Vec::new(),
+            vec![lfs.clone()],
+            time("2024-01-02T00:00:00Z"),
+        );
+
+        assert!(state.is_up_to_date(&instance(), &pr, &[], &[]));
+        assert!(state.is_up_to_date(&instance(), &pr, &[], &[lfs.clone()]));
+        assert!(!state.is_up_to_date(&instance(), &pr, &[], &[lfs, "wikis".to_string()]));
+    }
+
+    #[test]
+    fn group_wiki_refs() {
+        let mut state = State::default();
+        let backups = vec!["https://backup.example.com/".to_string()];
+        let refs = BTreeMap::from([("refs/heads/main".to_string(), "a1".to_string())]);
+        assert!(!state.is_group_wiki_up_to_date(&instance(), "team", &refs, &backups));
+
+        state.record_group_wiki(
+            &instance(),
+            "team",
+            refs.clone(),
+            backups.clone(),
+            time("2024-01-01T10:00:00Z"),
+        );
+        assert!(state.is_group_wiki_up_to_date(&instance(), "team", &refs, &backups));
+        assert!(!state.is_group_wiki_up_to_date(&instance(), "team/sub", &refs, &backups));
+
+        let changed = BTreeMap::from([("refs/heads/main".to_string(), "b2".to_string())]);
+        assert!(!state.is_group_wiki_up_to_date(&instance(), "team", &changed, &backups));
+        assert!(!state.is_group_wiki_up_to_date(
+            &instance(),
+            "team",
+            &refs,
+            &["https://other.example.com/".to_string()]
+        ));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dst = std::env::temp_dir().join(format!("gitlobster-state-{}", std::process::id()));
+        let dst = dst.to_str().unwrap();
+        let pr = project("2024-01-01T10:00:00Z");
+
+        assert!(!State::load(dst)
+            .unwrap()
+            .is_up_to_date(&instance(), &pr, &[], &[]));
+
+        let mut state = State::default();
+        state.record(
+            &instance(),
+            &pr,
+            Vec::new(),
+            Vec::new(),
+            time("2024-01-02T00:00:00Z"),
+        );
+        state.save(dst).unwrap();
+        assert!(State::load(dst)
+            .unwrap()
+            .is_up_to_date(&instance(), &pr, &[], &[]));
+
+        std::fs::remove_dir_all(dst).unwrap();
+    }
+}